@@ -1,11 +1,29 @@
 //! An abstraction to interact with the Bugzilla API.
 
+use crate::http;
 use anyhow::Context;
-use serde::Deserialize;
+use reqwest::{
+    header::{HeaderMap, HeaderValue},
+    StatusCode,
+};
+use serde::{de::IntoDeserializer, Deserialize, Deserializer, Serialize};
 use std::collections::HashMap;
 use thiserror::Error;
 
-const BZ_API: &str = "https://bugzilla.mozilla.org/rest";
+/// The Bugzilla instance used when none is configured.
+pub const DEFAULT_URL: &str = "https://bugzilla.mozilla.org";
+
+/// The header used to authenticate requests with an API key.
+const API_KEY_HEADER: &str = "X-BUGZILLA-API-KEY";
+
+/// The Bugzilla error code returned when the user is not allowed to see a bug.
+const ACCESS_DENIED_CODE: i64 = 102;
+
+/// The most bugs to request at once, to keep URLs to a reasonable length.
+const MAX_BATCH_SIZE: usize = 100;
+
+/// The fields of a bug that are needed to build a `BugDetail`.
+const DETAIL_FIELDS: &str = "id,status,resolution";
 
 /// A problem that prevents usage of the Bugzilla API.
 #[derive(Error, Debug)]
@@ -14,6 +32,27 @@ pub enum Error {
     #[error("The API did not return the expected information")]
     ApiContract,
 
+    /// The bug is confidential and the configured credentials cannot access it
+    #[error("Access denied to bug {0}")]
+    AccessDenied(String),
+
+    /// The API reported an error
+    #[error("Bugzilla error {code}: {message}")]
+    Api {
+        /// The Bugzilla error code.
+        code: i64,
+        /// A description of the error from Bugzilla.
+        message: String,
+    },
+
+    /// The API responded with an unexpected HTTP status
+    #[error("Bugzilla responded with status {0}")]
+    Status(StatusCode),
+
+    /// The configured API key cannot be sent as a header
+    #[error("The API key contains characters that are not allowed in a header")]
+    InvalidApiKey,
+
     /// Could not complete API request
     #[error("Could not complete API request")]
     Http(#[from] reqwest::Error),
@@ -25,76 +64,347 @@ pub enum Error {
 
 type Result<T> = std::result::Result<T, Error>;
 
-/// A Bugzilla bug.
+/// Settings describing how to reach a Bugzilla instance.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// The base URL of the Bugzilla instance, without the trailing `/rest`.
+    pub url: String,
+
+    /// How to make requests to the instance.
+    pub http: http::Settings,
+
+    /// An API key used to access confidential bugs, if any.
+    pub api_key: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            url: DEFAULT_URL.to_string(),
+            http: http::Settings::default(),
+            api_key: None,
+        }
+    }
+}
+
+/// An HTTP client bound to a specific Bugzilla instance.
 #[derive(Debug)]
+pub struct Client {
+    http: reqwest::Client,
+    rest_url: String,
+}
+
+impl Client {
+    /// Create a client that talks to the Bugzilla instance described by `config`.
+    ///
+    /// # Errors
+    /// Returns an error if the API key is not a valid header value, or if the
+    /// underlying HTTP client cannot be constructed.
+    pub fn new(config: &Config) -> Result<Self> {
+        let mut headers = HeaderMap::new();
+        if let Some(api_key) = &config.api_key {
+            let mut value = HeaderValue::from_str(api_key).map_err(|_| Error::InvalidApiKey)?;
+            value.set_sensitive(true);
+            headers.insert(API_KEY_HEADER, value);
+        }
+        let http = config
+            .http
+            .client_builder()
+            .default_headers(headers)
+            .build()?;
+        Ok(Self {
+            http,
+            rest_url: format!("{}/rest", config.url.trim_end_matches('/')),
+        })
+    }
+
+    /// Fetch the details of many bugs, using as few requests as possible. Bug
+    /// IDs that are requested more than once are only fetched once.
+    ///
+    /// Every requested ID is present in the returned map. Bugs that could not
+    /// be fetched, for example because they are confidential, map to an error.
+    ///
+    /// # Errors
+    /// Returns an error if an API request fails or cannot be parsed.
+    pub async fn details_many<I>(&self, ids: I) -> Result<HashMap<u64, Result<BugDetail>>>
+    where
+        I: IntoIterator<Item = u64>,
+    {
+        let mut ids: Vec<u64> = ids.into_iter().collect();
+        ids.sort_unstable();
+        ids.dedup();
+
+        let mut results = HashMap::with_capacity(ids.len());
+        for chunk in ids.chunks(MAX_BATCH_SIZE) {
+            let joined = chunk
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(",");
+            let request = self.http.get(format!("{}/bug", self.rest_url)).query(&[
+                ("id", joined.as_str()),
+                ("include_fields", DETAIL_FIELDS),
+                ("permissive", "1"),
+            ]);
+            let res = self.send(request, &joined).await?;
+            let data: ApiBatchResponse = res
+                .json()
+                .await
+                .context(format!("Failed to fetch details for bugs {joined}"))?;
+
+            for detail in data.bugs {
+                results.insert(detail.id, Ok(detail));
+            }
+            for fault in data.faults {
+                let Some(id) = fault.id.value() else {
+                    continue;
+                };
+                results.insert(
+                    id,
+                    Err(fault_error(fault.code, fault.message, &id.to_string())),
+                );
+            }
+        }
+
+        for id in ids {
+            results.entry(id).or_insert(Err(Error::ApiContract));
+        }
+
+        Ok(results)
+    }
+
+    /// Send a request concerning `bug_id`, turning error responses into an
+    /// appropriate `Error`.
+    async fn send(
+        &self,
+        request: reqwest::RequestBuilder,
+        bug_id: &str,
+    ) -> Result<reqwest::Response> {
+        let res = Box::pin(request.send()).await?;
+        let status = res.status();
+        if status.is_success() {
+            return Ok(res);
+        }
+
+        let fault = Box::pin(res.json::<ApiFault>()).await.ok();
+        Err(response_error(status, fault, bug_id))
+    }
+}
+
+/// Turn a fault that the API reported concerning `bug_id` into an `Error`.
+fn fault_error(code: i64, message: String, bug_id: &str) -> Error {
+    if code == ACCESS_DENIED_CODE {
+        Error::AccessDenied(bug_id.to_string())
+    } else {
+        Error::Api { code, message }
+    }
+}
+
+/// Turn an error response concerning `bug_id` into an `Error`, using the
+/// fault in its body if it had one.
+fn response_error(status: StatusCode, fault: Option<ApiFault>, bug_id: &str) -> Error {
+    match fault {
+        Some(fault) => fault_error(fault.code, fault.message, bug_id),
+        None if status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN => {
+            Error::AccessDenied(bug_id.to_string())
+        }
+        None => Error::Status(status),
+    }
+}
+
+/// A Bugzilla bug.
+#[derive(Copy, Clone, Debug)]
 pub struct Bug {
     /// The ID of the bug.
-    pub id: String,
+    pub id: u64,
 }
 
 impl Bug {
     /// Create a new bug.
     #[must_use]
-    pub const fn new(id: String) -> Self {
+    pub const fn new(id: u64) -> Self {
         Self { id }
     }
 
-    /// Bind an HTTP client to this bug so that more information can be pulled from the API.
+    /// Bind a Bugzilla client to this bug so that more information can be pulled from the API.
     #[must_use]
     #[allow(clippy::missing_const_for_fn)] // Since this drops `self`, it in fact cannot be a `const fn`.
-    pub fn with_api(self, client: &reqwest::Client) -> ApiBug {
+    pub fn with_api(self, client: &Client) -> ApiBug<'_> {
         ApiBug::new(client, self.id)
     }
 }
 
 /// More detailed information about a bug pulled from the API.
 #[allow(missing_copy_implementations)]
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct BugDetail {
+    /// The ID of the bug.
+    pub id: u64,
+
     /// The status of the bug, such as RESOLVED, or NEW.
     pub status: BugStatus,
+
+    /// The resolution of the bug, such as FIXED or WONTFIX. Bugs that are not
+    /// resolved have no resolution.
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub resolution: Option<BugResolution>,
+}
+
+impl BugDetail {
+    /// Whether the bug has been resolved as fixed, meaning the work it tracks has landed.
+    #[must_use]
+    pub fn is_fixed(&self) -> bool {
+        self.status.is_resolved() && self.resolution == Some(BugResolution::Fixed)
+    }
 }
 
 /// The status of a bug, such as RESOLVED, or NEW.
-#[derive(Copy, Clone, Debug, Deserialize, PartialEq)]
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
 #[serde(rename_all = "UPPERCASE")]
 pub enum BugStatus {
+    /// This bug has been reported, but not yet confirmed by anyone else.
+    Unconfirmed,
+
     /// This bug has recently been added to the list of bugs.
     New,
 
+    /// Someone has taken responsibility for this bug.
+    Assigned,
+
+    /// This bug was resolved, but it has been reopened.
+    Reopened,
+
     /// A resolution has been performed, and it is awaiting verification.
     Resolved,
 
     /// The resolution of the bug has been verified.
     Verified,
+
+    /// The bug is considered dead and will not be reopened.
+    Closed,
+
+    /// A status that this tool doesn't know about, such as a custom workflow status.
+    #[serde(other)]
+    Unknown,
+}
+
+impl BugStatus {
+    /// Whether this status indicates that the bug has a resolution.
+    #[must_use]
+    pub const fn is_resolved(self) -> bool {
+        matches!(self, Self::Resolved | Self::Verified | Self::Closed)
+    }
+}
+
+/// The resolution of a bug, such as FIXED, or WONTFIX.
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum BugResolution {
+    /// The work tracked by the bug has been completed.
+    Fixed,
+
+    /// The problem described is not a bug.
+    Invalid,
+
+    /// The problem described is a bug which will never be fixed.
+    Wontfix,
+
+    /// The problem is a duplicate of an existing bug.
+    Duplicate,
+
+    /// The problem could not be reproduced.
+    Worksforme,
+
+    /// The bug did not contain enough information to act on.
+    Incomplete,
+
+    /// The bug was moved to another bug tracker.
+    Moved,
+
+    /// The bug has not been acted on in a long time.
+    Inactive,
+
+    /// A resolution that this tool doesn't know about.
+    #[serde(other)]
+    Unknown,
+}
+
+/// Bugzilla represents missing values, such as the resolution of an open bug,
+/// as an empty string. Treat those as `None`.
+fn empty_as_none<'de, D, T>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    match Option::<String>::deserialize(deserializer)?.as_deref() {
+        None | Some("") => Ok(None),
+        Some(raw) => T::deserialize(raw.into_deserializer()).map(Some),
+    }
 }
 
 /// A comment posted on a bug.
-#[derive(Debug, Deserialize)]
+#[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct Comment {
     /// The global ID of the comment.
     pub id: u32,
     /// The unformatted text of the comment.
     pub raw_text: String,
+    /// When the comment was posted, as an ISO 8601 timestamp.
+    pub creation_time: String,
 }
 
-/// A Bugzilla bug that has been associated with an HTTP client for further API queries.
+/// A Bugzilla bug that has been associated with a Bugzilla client for further API queries.
 #[derive(Debug)]
 pub struct ApiBug<'a> {
     /// The ID of the bug.
-    pub id: String,
+    pub id: u64,
 
-    client: &'a reqwest::Client,
+    client: &'a Client,
 }
 
 #[derive(Debug, Deserialize)]
-struct ApiMapResponse<T> {
-    bugs: HashMap<String, T>,
+struct ApiFault {
+    code: i64,
+    message: String,
+}
+
+/// A bug that could not be fetched as part of a batch request.
+#[derive(Debug, Deserialize)]
+struct BugFault {
+    id: BugId,
+    #[serde(rename = "faultCode")]
+    code: i64,
+    #[serde(rename = "faultString")]
+    message: String,
 }
 
+/// Bugzilla isn't consistent about whether bug IDs are numbers or strings.
 #[derive(Debug, Deserialize)]
-struct ApiListResponse<T> {
-    bugs: Vec<T>,
+#[serde(untagged)]
+enum BugId {
+    Number(u64),
+    String(String),
+}
+
+impl BugId {
+    fn value(&self) -> Option<u64> {
+        match self {
+            Self::Number(id) => Some(*id),
+            Self::String(id) => id.parse().ok(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiBatchResponse {
+    bugs: Vec<BugDetail>,
+    #[serde(default)]
+    faults: Vec<BugFault>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiMapResponse<T> {
+    bugs: HashMap<String, T>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -103,44 +413,117 @@ struct BugComments {
 }
 
 impl<'a> ApiBug<'a> {
-    const fn new(client: &'a reqwest::Client, id: String) -> Self {
+    const fn new(client: &'a Client, id: u64) -> Self {
         Self { id, client }
     }
 
-    /// Fetch the details of this bug from the API.
+    /// Fetch all comments on this bug from the API.
     ///
     /// # Errors
-    /// Returns an error if the API request fails or cannot be parsed.
-    pub async fn details(&self) -> Result<BugDetail> {
-        let url = format!("{}/bug/{}", BZ_API, self.id);
-        let res = self.client.get(url).send().await?;
-        let mut data: ApiListResponse<BugDetail> = res
-            .json()
-            .await
-            .context(format!("Failed to fetch details for bug {}", self.id))?;
-        Ok(data
-            .bugs
-            .pop()
-            .ok_or(Error::ApiContract)
-            .context("No bugs in response")?)
+    /// Returns an error if the API request fails or cannot be parsed. Returns
+    /// `Error::AccessDenied` if the bug is confidential and cannot be read.
+    pub async fn comments(&self) -> Result<Vec<Comment>> {
+        self.fetch_comments(None).await
     }
 
-    /// Fetch all comments on this bug from the API.
+    /// Fetch the comments on this bug that were posted after `since`, an ISO
+    /// 8601 timestamp such as the `creation_time` of a comment.
     ///
     /// # Errors
-    /// Returns an error if the API request fails or cannot be parsed.
-    pub async fn comments(&self) -> Result<Vec<Comment>> {
-        let url = format!("{}/bug/{}/comment", BZ_API, self.id);
-        let res = self.client.get(url).send().await?;
+    /// Returns an error if the API request fails or cannot be parsed. Returns
+    /// `Error::AccessDenied` if the bug is confidential and cannot be read.
+    pub async fn comments_since(&self, since: &str) -> Result<Vec<Comment>> {
+        self.fetch_comments(Some(since)).await
+    }
+
+    async fn fetch_comments(&self, since: Option<&str>) -> Result<Vec<Comment>> {
+        let url = format!("{}/bug/{}/comment", self.client.rest_url, self.id);
+        let mut request = self.client.http.get(url);
+        if let Some(since) = since {
+            request = request.query(&[("new_since", since)]);
+        }
+        let res = self.client.send(request, &self.id.to_string()).await?;
         let mut data: ApiMapResponse<BugComments> = res
             .json()
             .await
             .context(format!("Failed to fetch comments for bug {}", self.id))?;
         Ok(data
             .bugs
-            .remove(&self.id)
+            .remove(&self.id.to_string())
             .ok_or(Error::ApiContract)
             .context("API fault: requested bug not in response")?
             .comments)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        fault_error, response_error, ApiFault, BugDetail, BugResolution, BugStatus, Error,
+        ACCESS_DENIED_CODE,
+    };
+    use reqwest::StatusCode;
+
+    fn detail(json: &str) -> BugDetail {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn fixed() {
+        let bug = detail(r#"{"id": 1, "status": "RESOLVED", "resolution": "FIXED"}"#);
+        assert_eq!(bug.status, BugStatus::Resolved);
+        assert_eq!(bug.resolution, Some(BugResolution::Fixed));
+        assert!(bug.is_fixed());
+    }
+
+    #[test]
+    fn empty_resolution() {
+        let bug = detail(r#"{"id": 1, "status": "NEW", "resolution": ""}"#);
+        assert_eq!(bug.resolution, None);
+        assert!(!bug.is_fixed());
+
+        let bug = detail(r#"{"id": 1, "status": "NEW"}"#);
+        assert_eq!(bug.resolution, None);
+    }
+
+    #[test]
+    fn unknown_status_and_resolution() {
+        let bug = detail(r#"{"id": 1, "status": "IN_REVIEW", "resolution": ""}"#);
+        assert_eq!(bug.status, BugStatus::Unknown);
+        assert!(!bug.status.is_resolved());
+
+        let bug = detail(r#"{"id": 1, "status": "RESOLVED", "resolution": "SHIPPED"}"#);
+        assert_eq!(bug.resolution, Some(BugResolution::Unknown));
+        assert!(!bug.is_fixed());
+    }
+
+    #[test]
+    fn access_denied_fault() {
+        let err = fault_error(ACCESS_DENIED_CODE, "denied".to_string(), "7");
+        assert!(matches!(err, Error::AccessDenied(id) if id == "7"));
+
+        let err = fault_error(101, "invalid bug".to_string(), "7");
+        assert!(matches!(err, Error::Api { code: 101, .. }));
+    }
+
+    #[test]
+    fn access_denied_response() {
+        let fault = ApiFault {
+            code: ACCESS_DENIED_CODE,
+            message: "denied".to_string(),
+        };
+        let err = response_error(StatusCode::BAD_REQUEST, Some(fault), "7");
+        assert!(matches!(err, Error::AccessDenied(id) if id == "7"));
+
+        for status in [StatusCode::UNAUTHORIZED, StatusCode::FORBIDDEN] {
+            let err = response_error(status, None, "7");
+            assert!(matches!(err, Error::AccessDenied(id) if id == "7"));
+        }
+
+        let err = response_error(StatusCode::INTERNAL_SERVER_ERROR, None, "7");
+        assert!(matches!(
+            err,
+            Error::Status(StatusCode::INTERNAL_SERVER_ERROR)
+        ));
+    }
+}
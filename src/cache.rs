@@ -0,0 +1,312 @@
+//! A persistent, on-disk cache of Bugzilla responses.
+
+use crate::bz::{self, ApiBug, BugDetail, Comment};
+use async_std::{fs, io, path::PathBuf};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU32, Ordering},
+    time::{Duration, SystemTime},
+};
+use thiserror::Error;
+
+/// The name of the directory, within the platform's cache directory, that holds cached data.
+const CACHE_DIR: &str = "hg-bz-prune";
+
+/// How long cached bug details are trusted before they are fetched again.
+const DETAILS_TTL: Duration = Duration::from_hours(1);
+
+/// A problem that prevented data from being read from the cache or the API.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// The bug is not in the cache, and the cache is being used offline
+    #[error("Bug {0} is not cached")]
+    NotCached(u64),
+
+    /// Could not read or write the cache
+    #[error("Could not access cache")]
+    Io(#[from] io::Error),
+
+    /// A cache entry could not be serialized
+    #[error("Cache entry could not be serialized")]
+    Serialize(#[from] serde_json::Error),
+
+    /// Errors from the Bugzilla API are passed through transparently.
+    #[error(transparent)]
+    Bz(#[from] bz::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// How the cache should be used.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Use cached data while it is fresh, and only fetch what is new.
+    Normal,
+
+    /// Ignore cached data, fetch everything again, and store the results.
+    Refresh,
+
+    /// Only use cached data, and never contact the API.
+    Offline,
+}
+
+/// A value, along with when it was fetched.
+#[derive(Debug, Deserialize, Serialize)]
+struct Timestamped<T> {
+    fetched: SystemTime,
+    value: T,
+}
+
+impl<T> Timestamped<T> {
+    fn now(value: T) -> Self {
+        Self {
+            fetched: SystemTime::now(),
+            value,
+        }
+    }
+
+    fn is_fresh(&self, ttl: Duration) -> bool {
+        self.fetched.elapsed().is_ok_and(|elapsed| elapsed < ttl)
+    }
+}
+
+/// Everything stored about a single bug.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Entry {
+    details: Option<Timestamped<BugDetail>>,
+    comments: Option<Timestamped<Vec<Comment>>>,
+}
+
+/// A Bugzilla client that stores responses on disk, and reuses them on later runs.
+#[derive(Debug)]
+pub struct CachingClient<'a> {
+    client: &'a bz::Client,
+    dir: PathBuf,
+    mode: Mode,
+    writes: AtomicU32,
+}
+
+impl<'a> CachingClient<'a> {
+    /// Wrap `client`, storing responses in `dir`.
+    pub fn new<P: Into<PathBuf>>(client: &'a bz::Client, dir: P, mode: Mode) -> Self {
+        Self {
+            client,
+            dir: dir.into(),
+            mode,
+            writes: AtomicU32::new(0),
+        }
+    }
+
+    /// The default location of the cache for the Bugzilla instance at `url`,
+    /// in the platform's cache directory. Each instance has its own cache, since
+    /// bug IDs are only unique within an instance.
+    #[must_use]
+    pub fn default_dir(url: &str) -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| {
+            PathBuf::from(dir)
+                .join(CACHE_DIR)
+                .join(instance_dir(url))
+                .join("bugs")
+        })
+    }
+
+    /// Fetch the details of many bugs, as `bz::Client::details_many` does.
+    /// Details that were cached recently are used without contacting the API.
+    ///
+    /// # Errors
+    /// Returns an error if the cache cannot be written, or if an API request
+    /// fails or cannot be parsed.
+    pub async fn details_many<I>(&self, ids: I) -> Result<HashMap<u64, Result<BugDetail>>>
+    where
+        I: IntoIterator<Item = u64>,
+    {
+        let mut results = HashMap::new();
+        let mut stale = Vec::new();
+
+        for id in ids {
+            if results.contains_key(&id) || stale.contains(&id) {
+                continue;
+            }
+            let cached = match self.mode {
+                Mode::Refresh => None,
+                Mode::Normal | Mode::Offline => self.load(id).await.details,
+            };
+            match cached {
+                Some(details) if self.mode == Mode::Offline || details.is_fresh(DETAILS_TTL) => {
+                    results.insert(id, Ok(details.value));
+                }
+                _ if self.mode == Mode::Offline => {
+                    results.insert(id, Err(Error::NotCached(id)));
+                }
+                _ => stale.push(id),
+            }
+        }
+
+        if !stale.is_empty() {
+            for (id, fetched) in self.client.details_many(stale).await? {
+                if let Ok(details) = &fetched {
+                    let mut entry = self.load(id).await;
+                    entry.details = Some(Timestamped::now(details.clone()));
+                    self.store(id, &entry).await?;
+                }
+                results.insert(id, fetched.map_err(Error::from));
+            }
+        }
+
+        Ok(results)
+    }
+
+    /// Fetch all comments on `bug`. If comments were cached by an earlier run,
+    /// only comments posted since the last cached comment are fetched.
+    ///
+    /// # Errors
+    /// Returns an error if the bug is not cached while offline, if the cache
+    /// cannot be written, or if an API request fails or cannot be parsed.
+    pub async fn comments(&self, bug: &ApiBug<'_>) -> Result<Vec<Comment>> {
+        let mut entry = self.load(bug.id).await;
+        let cached = match self.mode {
+            Mode::Refresh => None,
+            Mode::Normal | Mode::Offline => entry.comments.take(),
+        };
+
+        let comments = match (self.mode, cached) {
+            (Mode::Offline, Some(cached)) => return Ok(cached.value),
+            (Mode::Offline, None) => return Err(Error::NotCached(bug.id)),
+            (
+                _,
+                Some(Timestamped {
+                    value: mut comments,
+                    ..
+                }),
+            ) => match comments.last().map(|last| last.creation_time.clone()) {
+                Some(since) => {
+                    merge_comments(&mut comments, bug.comments_since(&since).await?);
+                    comments
+                }
+                None => bug.comments().await?,
+            },
+            (_, None) => bug.comments().await?,
+        };
+
+        entry.comments = Some(Timestamped::now(comments.clone()));
+        self.store(bug.id, &entry).await?;
+        Ok(comments)
+    }
+
+    fn path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("{id}.json"))
+    }
+
+    /// Read the cache entry for a bug. Missing or unreadable entries are
+    /// treated as empty, since they will be replaced with fresh data.
+    async fn load(&self, id: u64) -> Entry {
+        fs::read(self.path(id))
+            .await
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default()
+    }
+
+    /// Write the cache entry for a bug. The entry is written to a temporary
+    /// file first, so that concurrent readers never see a partial entry.
+    async fn store(&self, id: u64, entry: &Entry) -> Result<()> {
+        fs::create_dir_all(&self.dir).await?;
+        let serial = self.writes.fetch_add(1, Ordering::SeqCst);
+        let tmp = self
+            .dir
+            .join(format!(".{id}.{}.{serial}.tmp", std::process::id()));
+        fs::write(&tmp, serde_json::to_vec(entry)?).await?;
+        fs::rename(&tmp, self.path(id)).await?;
+        Ok(())
+    }
+}
+
+/// Name the cache directory of the Bugzilla instance at `url` after its host
+/// and path, replacing anything that isn't safe in a file name.
+fn instance_dir(url: &str) -> String {
+    let url = url.split_once("://").map_or(url, |(_, rest)| rest);
+    url.trim_end_matches('/')
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Add comments fetched since the last cached comment to `comments`. The last
+/// cached comment is fetched again, since comments are fetched from its
+/// creation time onwards, so comments that are already cached are skipped.
+fn merge_comments(comments: &mut Vec<Comment>, new: Vec<Comment>) {
+    for comment in new {
+        if comments.iter().all(|c| c.id != comment.id) {
+            comments.push(comment);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{instance_dir, merge_comments, Timestamped, DETAILS_TTL};
+    use crate::bz::Comment;
+    use std::time::{Duration, SystemTime};
+
+    fn comment(id: u32) -> Comment {
+        Comment {
+            id,
+            raw_text: format!("Comment {id}"),
+            creation_time: format!("2020-01-01T00:00:{id:02}Z"),
+        }
+    }
+
+    #[test]
+    fn freshness() {
+        assert!(Timestamped::now(()).is_fresh(DETAILS_TTL));
+
+        let stale = Timestamped {
+            fetched: SystemTime::now() - DETAILS_TTL - Duration::from_secs(1),
+            value: (),
+        };
+        assert!(!stale.is_fresh(DETAILS_TTL));
+
+        // A timestamp from the future, such as after the clock changes, can't
+        // be trusted either.
+        let future = Timestamped {
+            fetched: SystemTime::now() + Duration::from_secs(30),
+            value: (),
+        };
+        assert!(!future.is_fresh(DETAILS_TTL));
+    }
+
+    #[test]
+    fn merge_new_comments() {
+        let mut comments = vec![comment(1), comment(2)];
+        merge_comments(&mut comments, vec![comment(2), comment(3), comment(4)]);
+        let ids: Vec<u32> = comments.iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn merge_nothing_new() {
+        let mut comments = vec![comment(1), comment(2)];
+        merge_comments(&mut comments, vec![comment(2)]);
+        assert_eq!(comments.len(), 2);
+    }
+
+    #[test]
+    fn instance_dirs() {
+        assert_eq!(
+            instance_dir("https://bugzilla.mozilla.org"),
+            "bugzilla.mozilla.org"
+        );
+        assert_eq!(
+            instance_dir("https://bugzilla.example.com/bugzilla/"),
+            "bugzilla.example.com_bugzilla"
+        );
+        assert_eq!(instance_dir("http://127.0.0.1:8765"), "127.0.0.1_8765");
+    }
+}
@@ -0,0 +1,255 @@
+//! A client for Mercurial's command server, which runs many commands in one
+//! long-lived process instead of paying for Python startup every time.
+
+use crate::hg::Output;
+use async_std::{
+    io::{self, prelude::*},
+    path::Path,
+    process::{ChildStdin, ChildStdout, Command, Stdio},
+};
+use std::{convert::TryFrom, ffi::OsStr};
+use thiserror::Error;
+
+/// The capability a command server must advertise to run commands.
+const RUNCOMMAND: &str = "runcommand";
+
+/// How the line listing a command server's capabilities begins.
+const CAPABILITIES_PREFIX: &str = "capabilities:";
+
+/// A problem communicating with a command server.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// Could not start or talk to the command server
+    #[error("Could not communicate with the command server")]
+    Io(#[from] io::Error),
+
+    /// The command server can't run commands
+    #[error("The command server does not support running commands")]
+    Unsupported,
+
+    /// The command server required something that isn't supported
+    #[error("Unexpected request on channel {0:?} from the command server")]
+    UnexpectedChannel(char),
+
+    /// The command server sent a message that couldn't be understood
+    #[error("Malformed message from the command server")]
+    Malformed,
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A running `hg serve --cmdserver pipe` process.
+#[derive(Debug)]
+pub struct CommandServer {
+    stdin: ChildStdin,
+    stdout: ChildStdout,
+}
+
+impl CommandServer {
+    /// Start a command server for the repository at `repo_path`, and wait
+    /// for it to say that it is ready.
+    ///
+    /// # Errors
+    /// Returns an error if the server can't be started, or doesn't support
+    /// running commands.
+    pub async fn start(repo_path: &Path) -> Result<Self> {
+        let mut child = Command::new("hg")
+            .arg("-R")
+            .arg(repo_path)
+            .args(["serve", "--cmdserver", "pipe"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        // The server exits once its stdin is closed, so the child itself
+        // doesn't need to be kept.
+        let (Some(stdin), Some(mut stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(Error::Malformed);
+        };
+
+        let hello = read_message(&mut stdout).await?;
+        if hello.channel != b'o' || !supports_runcommand(&hello.data) {
+            return Err(Error::Unsupported);
+        }
+
+        Ok(Self { stdin, stdout })
+    }
+
+    /// Run a Mercurial command, as if `hg` had been run with `args`.
+    ///
+    /// # Errors
+    /// Returns an error if communicating with the server fails. A command
+    /// that fails is not an error, and its exit status is returned instead.
+    pub async fn run_command<I, S>(&mut self, args: I) -> Result<Output>
+    where
+        I: IntoIterator<Item = S> + Send,
+        S: AsRef<OsStr>,
+    {
+        let args: Vec<String> = args
+            .into_iter()
+            .map(|arg| arg.as_ref().to_string_lossy().into_owned())
+            .collect();
+        let payload = args.join("\0");
+        let length = u32::try_from(payload.len()).map_err(|_| Error::Malformed)?;
+
+        self.stdin.write_all(b"runcommand\n").await?;
+        self.stdin.write_all(&length.to_be_bytes()).await?;
+        self.stdin.write_all(payload.as_bytes()).await?;
+        self.stdin.flush().await?;
+
+        read_result(&mut self.stdout, &mut self.stdin).await
+    }
+}
+
+/// A message from the command server.
+#[derive(Debug, PartialEq, Eq)]
+struct Message {
+    /// The channel the message was sent on, such as `o` for output.
+    channel: u8,
+
+    /// What was sent. Input channels send no data.
+    data: Vec<u8>,
+}
+
+/// Read a single message from the command server.
+async fn read_message<R: Read + Unpin>(reader: &mut R) -> Result<Message> {
+    let mut header = [0; 5];
+    reader.read_exact(&mut header).await?;
+    let channel = header[0];
+    let length = u32::from_be_bytes([header[1], header[2], header[3], header[4]]);
+
+    // Input channels use the length to say how much input is wanted, rather
+    // than sending anything.
+    let mut data = Vec::new();
+    if !channel.is_ascii_uppercase() {
+        data.resize(usize::try_from(length).map_err(|_| Error::Malformed)?, 0);
+        reader.read_exact(&mut data).await?;
+    }
+
+    Ok(Message { channel, data })
+}
+
+/// Collect the output of a command until the server sends its exit status.
+async fn read_result<R, W>(reader: &mut R, writer: &mut W) -> Result<Output>
+where
+    R: Read + Unpin,
+    W: Write + Unpin,
+{
+    let mut stdout = Vec::new();
+    let mut stderr = Vec::new();
+    loop {
+        let message = read_message(reader).await?;
+        match message.channel {
+            b'o' => stdout.extend(message.data),
+            b'e' => stderr.extend(message.data),
+            b'r' => {
+                let code =
+                    <[u8; 4]>::try_from(message.data.as_slice()).map_err(|_| Error::Malformed)?;
+                return Ok(Output {
+                    code: Some(i32::from_be_bytes(code)),
+                    stdout,
+                    stderr,
+                });
+            }
+            // Commands are never given input, so answer as if at end of file
+            b'I' | b'L' => {
+                writer.write_all(&0_u32.to_be_bytes()).await?;
+                writer.flush().await?;
+            }
+            channel if channel.is_ascii_uppercase() => {
+                return Err(Error::UnexpectedChannel(char::from(channel)));
+            }
+            // Other output, such as debug messages, can be ignored
+            _ => {}
+        }
+    }
+}
+
+/// Check whether the hello message of a command server says that it can run
+/// commands.
+fn supports_runcommand(hello: &[u8]) -> bool {
+    String::from_utf8_lossy(hello).lines().any(|line| {
+        line.strip_prefix(CAPABILITIES_PREFIX)
+            .is_some_and(|capabilities| capabilities.split_whitespace().any(|c| c == RUNCOMMAND))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_message, read_result, supports_runcommand, Error, Message};
+    use async_std::task;
+    use std::convert::TryFrom;
+
+    fn message(channel: u8, data: &[u8]) -> Vec<u8> {
+        let mut message = vec![channel];
+        message.extend(&u32::try_from(data.len()).unwrap().to_be_bytes());
+        message.extend(data);
+        message
+    }
+
+    #[test]
+    fn hello() {
+        let hello = b"capabilities: getencoding runcommand\nencoding: UTF-8\npid: 1234";
+        assert!(supports_runcommand(hello));
+        assert!(!supports_runcommand(
+            b"capabilities: getencoding\nencoding: UTF-8"
+        ));
+    }
+
+    #[test]
+    fn output_message() {
+        let bytes = message(b'o', b"hello");
+        let read = task::block_on(read_message(&mut bytes.as_slice())).unwrap();
+        assert_eq!(
+            read,
+            Message {
+                channel: b'o',
+                data: b"hello".to_vec()
+            }
+        );
+    }
+
+    #[test]
+    fn input_message_has_no_data() {
+        let mut bytes = vec![b'L'];
+        bytes.extend(&4096_u32.to_be_bytes());
+        let read = task::block_on(read_message(&mut bytes.as_slice())).unwrap();
+        assert_eq!(read.channel, b'L');
+        assert!(read.data.is_empty());
+    }
+
+    #[test]
+    fn result() {
+        let mut bytes = message(b'o', b"out ");
+        bytes.extend(message(b'd', b"debug"));
+        bytes.extend(message(b'e', b"err"));
+        bytes.extend(message(b'o', b"put"));
+        bytes.extend(message(b'r', &1_i32.to_be_bytes()));
+        let mut written = Vec::new();
+        let output = task::block_on(read_result(&mut bytes.as_slice(), &mut written)).unwrap();
+        assert_eq!(output.code, Some(1));
+        assert_eq!(output.stdout, b"out put");
+        assert_eq!(output.stderr, b"err");
+        assert!(written.is_empty());
+    }
+
+    #[test]
+    fn input_is_refused() {
+        let mut bytes = vec![b'L'];
+        bytes.extend(&4096_u32.to_be_bytes());
+        bytes.extend(message(b'r', &255_i32.to_be_bytes()));
+        let mut written = Vec::new();
+        let output = task::block_on(read_result(&mut bytes.as_slice(), &mut written)).unwrap();
+        assert_eq!(output.code, Some(255));
+        assert_eq!(written, 0_u32.to_be_bytes());
+    }
+
+    #[test]
+    fn unknown_required_channel() {
+        let mut bytes = vec![b'X'];
+        bytes.extend(&0_u32.to_be_bytes());
+        let mut written = Vec::new();
+        let result = task::block_on(read_result(&mut bytes.as_slice(), &mut written));
+        assert!(matches!(result, Err(Error::UnexpectedChannel('X'))));
+    }
+}
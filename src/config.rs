@@ -0,0 +1,500 @@
+//! Settings read from the user's and the repository's configuration files.
+
+use crate::{
+    bz,
+    hg::{self, Hg},
+    http, phab,
+    repository::{Location, Repositories, Repository},
+};
+use serde::Deserialize;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use thiserror::Error;
+
+/// The name of the directory, within the platform's configuration directory, that holds the config file.
+const CONFIG_DIR: &str = "hg-bz-prune";
+
+/// The name of the config file.
+const CONFIG_FILE: &str = "config.toml";
+
+/// The name of the config file within a repository's `.hg` directory.
+const REPO_CONFIG_FILE: &str = "hg-bz-prune.toml";
+
+/// The name of the file, in the user's home directory, where Arcanist stores API tokens.
+const ARCRC_FILE: &str = ".arcrc";
+
+/// A problem that prevented the configuration from being loaded.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// Could not read the config file
+    #[error("Could not read config file {}", path.display())]
+    Io {
+        /// The path of the file that could not be read.
+        path: PathBuf,
+        /// The underlying error.
+        source: io::Error,
+    },
+
+    /// The config file is not valid
+    #[error("Config file {} is not valid", path.display())]
+    Parse {
+        /// The path of the file that could not be parsed.
+        path: PathBuf,
+        /// The underlying error.
+        source: toml::de::Error,
+    },
+
+    /// A repository is configured with both a path and a remote
+    #[error("Repository {name} in {} can't have both a path and a remote", path.display())]
+    AmbiguousRepository {
+        /// The path of the file that configures the repository.
+        path: PathBuf,
+        /// The name of the repository.
+        name: String,
+    },
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The contents of a config file. Every setting is optional, and anything
+/// left unset falls back to a default.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Whether to pull before looking for drafts.
+    pub pull: Option<bool>,
+
+    /// Which revisions to consider, as a revset or the name of a preset.
+    pub rev: Option<String>,
+
+    /// How many revisions to examine at once.
+    pub jobs: Option<usize>,
+
+    /// Whether to prompt, prune without prompting, or only report.
+    pub mode: Option<Interaction>,
+
+    /// Whether to restack descendants that pruning leaves orphaned.
+    pub restack: Option<bool>,
+
+    /// Named revsets that can be used in place of a revset.
+    #[serde(default)]
+    pub revsets: BTreeMap<String, String>,
+
+    /// Settings for the Bugzilla instance to query.
+    #[serde(default)]
+    pub bugzilla: BugzillaConfig,
+
+    /// Settings for the Phabricator instance to query.
+    #[serde(default)]
+    pub phabricator: PhabricatorConfig,
+
+    /// The repositories that revisions are accepted as landing in, keyed by
+    /// their path on hg.mozilla.org. If none are configured, only
+    /// mozilla-central is accepted.
+    #[serde(default)]
+    pub repositories: BTreeMap<String, RepositoryConfig>,
+}
+
+/// How to decide whether prunable revisions are pruned.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Interaction {
+    /// Ask about each prunable revision.
+    Prompt,
+
+    /// Report what would be pruned, without pruning anything.
+    DryRun,
+
+    /// Prune revisions that satisfy the policy without asking.
+    Yes,
+}
+
+/// The `[bugzilla]` section of the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BugzillaConfig {
+    /// The base URL of the Bugzilla instance.
+    pub url: Option<String>,
+
+    /// How to make requests to the instance.
+    #[serde(flatten)]
+    pub http: HttpConfig,
+
+    /// An API key used to access confidential bugs.
+    pub api_key: Option<String>,
+}
+
+/// The `[phabricator]` section of the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PhabricatorConfig {
+    /// The base URL of the Phabricator instance.
+    pub url: Option<String>,
+
+    /// How to make requests to the instance.
+    #[serde(flatten)]
+    pub http: HttpConfig,
+
+    /// A Conduit API token.
+    pub api_token: Option<String>,
+}
+
+/// The HTTP settings shared by the `[bugzilla]` and `[phabricator]` sections
+/// of the config file.
+#[derive(Debug, Default, Deserialize)]
+pub struct HttpConfig {
+    /// The user agent to send with every request.
+    pub user_agent: Option<String>,
+
+    /// The request timeout, in seconds.
+    pub timeout: Option<u64>,
+
+    /// The connection timeout, in seconds.
+    pub connect_timeout: Option<u64>,
+}
+
+/// A table in the `[repositories]` section of the config file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RepositoryConfig {
+    /// A local repository that successors must be in, instead of the one
+    /// drafts are being pruned from.
+    pub path: Option<PathBuf>,
+
+    /// A remote repository that successors must be in, such as a path name
+    /// from hgrc or a URL.
+    pub remote: Option<String>,
+}
+
+impl Config {
+    /// The default location of the config file, in the platform's configuration directory.
+    #[must_use]
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join(CONFIG_DIR).join(CONFIG_FILE))
+    }
+
+    /// Load the user's config file at `path`, and then the config file of the
+    /// repository containing `repo`, whose settings take precedence. If no
+    /// path is given, the default location is used if a file exists there.
+    /// Files that don't exist are treated as empty.
+    ///
+    /// # Errors
+    /// Returns an error if a file cannot be read or is not valid.
+    pub fn load(path: Option<&Path>, repo: &Path) -> Result<Self> {
+        let user = path
+            .map(Path::to_path_buf)
+            .or_else(|| Self::default_path().filter(|path| path.exists()))
+            .map_or_else(|| Ok(Self::default()), |path| Self::read(&path))?;
+
+        let repo_path = hg::hg_dir(repo)
+            .map(|dir| dir.join(REPO_CONFIG_FILE))
+            .filter(|path| path.exists());
+        match repo_path {
+            Some(path) => Ok(user.merge(Self::read(&path)?)),
+            None => Ok(user),
+        }
+    }
+
+    /// Combine two configs, with settings from `over` taking precedence.
+    #[must_use]
+    pub fn merge(mut self, over: Self) -> Self {
+        self.revsets.extend(over.revsets);
+        self.repositories.extend(over.repositories);
+        Self {
+            pull: over.pull.or(self.pull),
+            rev: over.rev.or(self.rev),
+            jobs: over.jobs.or(self.jobs),
+            mode: over.mode.or(self.mode),
+            restack: over.restack.or(self.restack),
+            revsets: self.revsets,
+            bugzilla: self.bugzilla.merge(over.bugzilla),
+            phabricator: self.phabricator.merge(over.phabricator),
+            repositories: self.repositories,
+        }
+    }
+
+    /// Expand `rev` into a revset, if it is the name of a preset.
+    #[must_use]
+    pub fn revset<'a>(&'a self, rev: &'a str) -> &'a str {
+        self.revsets.get(rev).map_or(rev, String::as_str)
+    }
+
+    fn read(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path).map_err(|source| Error::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::parse(&contents, path)
+    }
+
+    /// Parse the contents of the config file at `path`.
+    fn parse(contents: &str, path: &Path) -> Result<Self> {
+        let config: Self = toml::from_str(contents).map_err(|source| Error::Parse {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        if let Some((name, _)) = config
+            .repositories
+            .iter()
+            .find(|(_, repository)| repository.path.is_some() && repository.remote.is_some())
+        {
+            return Err(Error::AmbiguousRepository {
+                path: path.to_path_buf(),
+                name: name.clone(),
+            });
+        }
+        Ok(config)
+    }
+
+    /// The repositories that revisions are accepted as landing in, where
+    /// `here` is the repository that drafts are being pruned from.
+    #[must_use]
+    pub fn repositories<'a>(&self, here: &'a Hg) -> Repositories<'a> {
+        if self.repositories.is_empty() {
+            return Repositories::mozilla_central(here);
+        }
+        let repositories = self
+            .repositories
+            .iter()
+            .map(|(name, repository)| Repository {
+                name: name.clone(),
+                location: match (&repository.path, &repository.remote) {
+                    (Some(path), _) => Location::Path(Hg::new(path.clone())),
+                    (None, Some(remote)) => Location::Remote(remote.clone()),
+                    (None, None) => Location::Here,
+                },
+            })
+            .collect();
+        Repositories::new(repositories, here)
+    }
+}
+
+impl BugzillaConfig {
+    /// Combine two sections, with settings from `over` taking precedence.
+    #[must_use]
+    pub fn merge(self, over: Self) -> Self {
+        Self {
+            url: over.url.or(self.url),
+            http: self.http.merge(over.http),
+            api_key: over.api_key.or(self.api_key),
+        }
+    }
+
+    /// Produce the settings for a Bugzilla client, filling anything not configured with defaults.
+    #[must_use]
+    pub fn to_client_config(&self) -> bz::Config {
+        let defaults = bz::Config::default();
+        bz::Config {
+            url: self.url.clone().unwrap_or(defaults.url),
+            http: self.http.to_settings(),
+            api_key: self.api_key.clone(),
+        }
+    }
+}
+
+impl PhabricatorConfig {
+    /// Combine two sections, with settings from `over` taking precedence.
+    #[must_use]
+    pub fn merge(self, over: Self) -> Self {
+        Self {
+            url: over.url.or(self.url),
+            http: self.http.merge(over.http),
+            api_token: over.api_token.or(self.api_token),
+        }
+    }
+
+    /// Produce the settings for a Phabricator client, filling anything not configured with defaults.
+    #[must_use]
+    pub fn to_client_config(&self) -> phab::Config {
+        let defaults = phab::Config::default();
+        phab::Config {
+            url: self.url.clone().unwrap_or(defaults.url),
+            http: self.http.to_settings(),
+            api_token: self.api_token.clone(),
+        }
+    }
+}
+
+impl HttpConfig {
+    /// Combine two sets of settings, with those from `over` taking precedence.
+    #[must_use]
+    pub fn merge(self, over: Self) -> Self {
+        Self {
+            user_agent: over.user_agent.or(self.user_agent),
+            timeout: over.timeout.or(self.timeout),
+            connect_timeout: over.connect_timeout.or(self.connect_timeout),
+        }
+    }
+
+    /// Produce the settings for an HTTP client, filling anything not configured with defaults.
+    #[must_use]
+    pub fn to_settings(&self) -> http::Settings {
+        let defaults = http::Settings::default();
+        http::Settings {
+            user_agent: self.user_agent.clone().unwrap_or(defaults.user_agent),
+            timeout: self.timeout.map_or(defaults.timeout, Duration::from_secs),
+            connect_timeout: self
+                .connect_timeout
+                .map_or(defaults.connect_timeout, Duration::from_secs),
+        }
+    }
+}
+
+/// Find the Conduit API token for the Phabricator instance at `url` in
+/// `~/.arcrc`, where Arcanist and moz-phab store it.
+#[must_use]
+pub fn arcrc_token(url: &str) -> Option<String> {
+    let path = dirs::home_dir()?.join(ARCRC_FILE);
+    let contents = fs::read_to_string(path).ok()?;
+    let arcrc: Arcrc = serde_json::from_str(&contents).ok()?;
+    arcrc
+        .hosts
+        .get(&format!("{}/api/", url.trim_end_matches('/')))
+        .map(|host| host.token.clone())
+}
+
+/// The parts of `~/.arcrc` that hold API tokens.
+#[derive(Debug, Deserialize)]
+struct Arcrc {
+    hosts: HashMap<String, ArcrcHost>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ArcrcHost {
+    token: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BugzillaConfig, Config, Error, Interaction};
+    use std::{path::Path, time::Duration};
+
+    fn parse(contents: &str) -> Config {
+        Config::parse(contents, Path::new("config.toml")).unwrap()
+    }
+
+    #[test]
+    fn precedence() {
+        let user = parse(
+            r#"
+            pull = false
+            rev = "mine"
+            jobs = 4
+            mode = "yes"
+
+            [revsets]
+            mine = "draft() and user(me)"
+            old = "draft() and date(-30)"
+
+            [bugzilla]
+            url = "https://bugzilla.example.com"
+            api_key = "user-key"
+            "#,
+        );
+        let repo = parse(
+            r#"
+            rev = "old"
+            mode = "dry-run"
+
+            [revsets]
+            mine = "draft() and user(me) and not public()"
+
+            [bugzilla]
+            url = "https://bugzilla.mozilla.org"
+            "#,
+        );
+        let cli = Config {
+            pull: Some(true),
+            mode: Some(Interaction::Prompt),
+            bugzilla: BugzillaConfig {
+                url: Some("http://127.0.0.1:8765".to_string()),
+                ..BugzillaConfig::default()
+            },
+            ..Config::default()
+        };
+
+        let config = user.merge(repo).merge(cli);
+        assert_eq!(config.pull, Some(true));
+        assert_eq!(config.rev.as_deref(), Some("old"));
+        assert_eq!(config.jobs, Some(4));
+        assert_eq!(config.mode, Some(Interaction::Prompt));
+        assert_eq!(config.restack, None);
+        assert_eq!(
+            config.revset("mine"),
+            "draft() and user(me) and not public()"
+        );
+        assert_eq!(config.revset("old"), "draft() and date(-30)");
+        assert_eq!(config.revset("draft()"), "draft()");
+        assert_eq!(
+            config.bugzilla.url.as_deref(),
+            Some("http://127.0.0.1:8765")
+        );
+        assert_eq!(config.bugzilla.api_key.as_deref(), Some("user-key"));
+    }
+
+    #[test]
+    fn empty_config_changes_nothing() {
+        let config = parse("jobs = 2").merge(Config::default());
+        assert_eq!(config.jobs, Some(2));
+        let config = Config::default().merge(parse("jobs = 2"));
+        assert_eq!(config.jobs, Some(2));
+    }
+
+    #[test]
+    fn ambiguous_repository() {
+        let result = Config::parse(
+            r#"
+            [repositories.autoland]
+            path = "../autoland"
+            remote = "autoland"
+            "#,
+            Path::new("config.toml"),
+        );
+        assert!(matches!(
+            result,
+            Err(Error::AmbiguousRepository { name, .. }) if name == "autoland"
+        ));
+    }
+
+    #[test]
+    fn unknown_settings_are_rejected() {
+        let result = Config::parse("pul = true", Path::new("config.toml"));
+        assert!(matches!(result, Err(Error::Parse { .. })));
+        let result = Config::parse("[bugzilla]\napi_token = \"x\"", Path::new("config.toml"));
+        assert!(matches!(result, Err(Error::Parse { .. })));
+    }
+
+    #[test]
+    fn http_settings() {
+        let config = parse(
+            r#"
+            [bugzilla]
+            user_agent = "bugzilla-agent"
+            timeout = 60
+
+            [phabricator]
+            connect_timeout = 5
+            "#,
+        )
+        .merge(parse(
+            r"
+            [bugzilla]
+            timeout = 90
+            ",
+        ));
+
+        let bugzilla = config.bugzilla.to_client_config();
+        assert_eq!(bugzilla.http.user_agent, "bugzilla-agent");
+        assert_eq!(bugzilla.http.timeout, Duration::from_secs(90));
+        assert_eq!(bugzilla.http.connect_timeout, Duration::from_secs(10));
+
+        let phabricator = config.phabricator.to_client_config();
+        assert_eq!(phabricator.http.timeout, Duration::from_secs(30));
+        assert_eq!(phabricator.http.connect_timeout, Duration::from_secs(5));
+    }
+}
@@ -1,15 +1,41 @@
 //! Tools to interact with Mercurial.
 
-use crate::bz::Bug;
+use crate::{
+    bz::Bug,
+    cmdserver::{self, CommandServer},
+};
 use async_std::{
     io,
     path::PathBuf,
-    process::{Command, Output, Stdio},
+    process::{self, Command, Stdio},
+    sync::Mutex,
 };
-use serde::Deserialize;
-use std::ffi::OsStr;
+use futures::stream::{self, Stream, StreamExt};
+use serde::{Deserialize, Deserializer};
+use std::{convert::TryFrom, ffi::OsStr, path::Path};
 use thiserror::Error;
 
+/// What Mercurial says when asked about a changeset a repository doesn't have.
+const UNKNOWN_REVISION: &str = "unknown revision";
+
+/// How the line linking a revision to its Phabricator revision begins.
+const DIFFERENTIAL_PREFIX: &str = "Differential Revision:";
+
+/// Prints each revision as a line of JSON. This has the same fields as
+/// `--template json`, along with the topic and obsolescence information that
+/// it leaves out.
+const REVISION_TEMPLATE: &str = concat!(
+    r#"\{"rev": {rev}, "node": {node|json}, "branch": {branch|json}, "#,
+    r#""phase": {phase|json}, "user": {author|json}, "date": {date|json}, "#,
+    r#""desc": {desc|json}, "#,
+    r#""bookmarks": [{join(bookmarks % "{bookmark|json}", ", ")}], "#,
+    r#""tags": [{join(tags % "{tag|json}", ", ")}], "#,
+    r#""parents": [{p1node|json}, {p2node|json}], "#,
+    r#""topic": {get(extras, "topic")|json}, "obsolete": {obsolete|json}, "#,
+    r#""instabilities": [{join(instabilities % "{instability|json}", ", ")}]}"#,
+    "\n"
+);
+
 /// An error that prevented a mercurial command from being processed.
 #[derive(Error, Debug)]
 pub enum Error {
@@ -30,9 +56,22 @@ pub enum Error {
     #[error("Output was not valid UTF-8")]
     Utf8(#[from] std::string::FromUtf8Error),
 
+    /// A revset could not be used to select revisions
+    #[error("Invalid revset {revset:?}: {message}")]
+    Revset {
+        /// The revset that was rejected.
+        revset: String,
+        /// What Mercurial said was wrong with it.
+        message: String,
+    },
+
     /// Mercurial output could not be parsed
     #[error("Mercurial output could not be parsed")]
     RevisionParse(#[from] serde_json::error::Error),
+
+    /// Errors from the command server are passed through transparently.
+    #[error(transparent)]
+    CommandServer(#[from] cmdserver::Error),
 }
 
 type Result<T> = std::result::Result<T, Error>;
@@ -46,35 +85,125 @@ impl Error {
     }
 }
 
+/// What a Mercurial command printed, and how it exited.
+#[derive(Debug)]
+pub struct Output {
+    /// The exit status, if the command exited normally.
+    pub code: Option<i32>,
+
+    /// What the command wrote to stdout.
+    pub stdout: Vec<u8>,
+
+    /// What the command wrote to stderr.
+    pub stderr: Vec<u8>,
+}
+
+impl Output {
+    /// Whether the command succeeded.
+    #[must_use]
+    pub const fn success(&self) -> bool {
+        matches!(self.code, Some(0))
+    }
+}
+
+impl From<process::Output> for Output {
+    fn from(output: process::Output) -> Self {
+        Self {
+            code: output.status.code(),
+            stdout: output.stdout,
+            stderr: output.stderr,
+        }
+    }
+}
+
+/// A command server that is started the first time a command is run.
+#[derive(Debug)]
+enum Server {
+    /// No command has been run yet.
+    NotStarted,
+
+    /// The server is running, and commands are sent to it.
+    Running(CommandServer),
+
+    /// The server couldn't be started, or stopped working, so every command
+    /// starts a new process instead.
+    Unavailable,
+}
+
 /// A handle to run Mercurial commands with.
 #[derive(Debug)]
 pub struct Hg {
     repo_path: PathBuf,
+    server: Option<Mutex<Server>>,
 }
 
 impl Hg {
-    /// Create an object to run commands on the passed repository.
+    /// Create an object to run commands on the passed repository, starting a
+    /// new `hg` process for every command.
     pub fn new<P: Into<PathBuf>>(repo_path: P) -> Self {
         Self {
             repo_path: repo_path.into(),
+            server: None,
         }
     }
 
-    async fn run_command<I, S>(&self, args: I) -> Result<String>
+    /// Create an object to run commands on the passed repository through a
+    /// single command server, which avoids Mercurial's startup cost on every
+    /// command. If the server can't be used, a new `hg` process is started
+    /// for every command instead.
+    ///
+    /// The server runs one command at a time.
+    pub fn with_command_server<P: Into<PathBuf>>(repo_path: P) -> Self {
+        Self {
+            repo_path: repo_path.into(),
+            server: Some(Mutex::new(Server::NotStarted)),
+        }
+    }
+
+    async fn output<I, S>(&self, args: I) -> Result<Output>
     where
         I: IntoIterator<Item = S> + Send,
         S: AsRef<OsStr>,
     {
-        let output = Command::new("hg")
+        if let Some(server) = &self.server {
+            let mut server = server.lock().await;
+            if matches!(*server, Server::NotStarted) {
+                *server = CommandServer::start(&self.repo_path)
+                    .await
+                    .map_or(Server::Unavailable, Server::Running);
+            }
+            if let Server::Running(command_server) = &mut *server {
+                // A command that was interrupted may or may not have run, so
+                // it isn't retried, but later commands don't use the server.
+                return match command_server.run_command(args).await {
+                    Ok(output) => Ok(output),
+                    Err(err) => {
+                        *server = Server::Unavailable;
+                        Err(err.into())
+                    }
+                };
+            }
+        }
+
+        Ok(Command::new("hg")
             .arg("-R")
             .arg(&self.repo_path)
             .args(args)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .output()
-            .await?;
+            .await?
+            .into())
+    }
 
-        if output.status.success() {
+    async fn run_command<I, S>(&self, args: I) -> Result<String>
+    where
+        I: IntoIterator<Item = S> + Send,
+        S: AsRef<OsStr>,
+    {
+        let output = self.output(args).await?;
+
+        if output.success() {
             Ok(String::from_utf8(output.stdout)?)
         } else {
             Err(Error::command_error(&output))
@@ -90,39 +219,388 @@ impl Hg {
         Ok(())
     }
 
+    /// Read a configuration value, such as `bugzilla.apikey`, from the
+    /// repository's and user's hgrc files. Returns `None` if it is not set.
+    ///
+    /// # Errors
+    /// Returns an error if Mercurial fails to read its configuration.
+    pub async fn config(&self, name: &str) -> Result<Option<String>> {
+        let output = self.output(vec!["config", name]).await?;
+
+        if output.success() {
+            let value = String::from_utf8(output.stdout)?.trim().to_string();
+            Ok(Some(value).filter(|v| !v.is_empty()))
+        } else if output.code == Some(1) && output.stderr.is_empty() {
+            // Mercurial exits with 1 and no message when the value is not set.
+            Ok(None)
+        } else {
+            Err(Error::command_error(&output))
+        }
+    }
+
     /// Get a list of revisions from the repository, optionally matching some revspec.
     ///
     /// # Errors
     /// Returns an error if Mercurial fails to list revisions, or if the data
     /// from Mercurial cannot be parsed.
     pub async fn log(&self, revspec: Option<&str>) -> Result<Vec<Revision>> {
-        let mut args = vec!["log", "--template", "json"];
+        let mut args = vec!["log", "--template", REVISION_TEMPLATE];
         if let Some(rev) = &revspec {
             args.push("--rev");
-            args.push(rev)
+            args.push(rev);
         }
         let output = self.run_command(args).await?;
 
-        serde_json::from_str(&output).map_err(Error::RevisionParse)
+        serde_json::Deserializer::from_str(&output)
+            .into_iter()
+            .collect::<std::result::Result<_, _>>()
+            .map_err(Error::RevisionParse)
     }
 
-    /// Prune a revision from the repository, marking it as obsolete. Optionally
-    /// mark another revision as having succeeded it.
+    /// Check that `revset` can be used to select revisions, so that mistakes
+    /// are reported before doing anything else.
     ///
     /// # Errors
-    /// Returns an error if Mercurial fails to prune the revision.
-    pub async fn prune(&self, rev: &str, successor: Option<&str>) -> Result<()> {
-        let mut args = vec!["prune", "--rev", rev];
-        if let Some(succ) = successor {
-            args.push("--succ");
-            args.push(succ);
+    /// Returns an error if Mercurial can't parse the revset, or it refers to
+    /// revisions that don't exist.
+    pub async fn validate_revset(&self, revset: &str) -> Result<()> {
+        let output = self
+            .output(vec![
+                "log",
+                "--rev",
+                revset,
+                "--limit",
+                "1",
+                "--template",
+                "",
+            ])
+            .await?;
+
+        if output.success() {
+            Ok(())
+        } else {
+            Err(Error::Revset {
+                revset: revset.to_string(),
+                message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            })
+        }
+    }
+
+    /// Check whether a changeset, identified by a full or abbreviated hash, is
+    /// present in the local repository.
+    ///
+    /// # Errors
+    /// Returns an error if Mercurial fails to look up the changeset, for
+    /// example because an abbreviated hash is ambiguous.
+    pub async fn is_known(&self, node: &str) -> Result<bool> {
+        let revset = format!("present({node})");
+        Ok(!self.log(Some(&revset)).await?.is_empty())
+    }
+
+    /// Check whether a changeset, identified by a full or abbreviated hash, is
+    /// present in a remote repository, such as a path name from hgrc or a URL.
+    ///
+    /// # Errors
+    /// Returns an error if Mercurial fails to contact the remote repository.
+    pub async fn remote_has(&self, remote: &str, node: &str) -> Result<bool> {
+        let output = self
+            .output(vec!["identify", "--id", "--rev", node, remote])
+            .await?;
+
+        if output.success() {
+            Ok(true)
+        } else if String::from_utf8_lossy(&output.stderr).contains(UNKNOWN_REVISION) {
+            Ok(false)
+        } else {
+            Err(Error::command_error(&output))
+        }
+    }
+
+    /// Get the revisions for any of `nodes`, identified by full or abbreviated
+    /// hashes, that are present in the local repository.
+    ///
+    /// # Errors
+    /// Returns an error if Mercurial fails to look up the changesets, for
+    /// example because an abbreviated hash is ambiguous.
+    pub async fn find_all<'a, I>(&self, nodes: I) -> Result<Vec<Revision>>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let revset: Vec<String> = nodes
+            .into_iter()
+            .map(|node| format!("present({node})"))
+            .collect();
+        if revset.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.log(Some(&revset.join(" + "))).await
+    }
+
+    /// Get the public changesets that might be the landed form of `revision`:
+    /// those added since the revision's public base that mention one of its
+    /// bugs or its Differential Revision, in the order they were added.
+    ///
+    /// # Errors
+    /// Returns an error if Mercurial fails to list the changesets.
+    pub async fn find_public(&self, revision: &Revision) -> Result<Vec<Revision>> {
+        match landed_revset(revision) {
+            Some(revset) => self.log(Some(&revset)).await,
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Get every revision that isn't public or obsolete, which are the ones
+    /// that stacks are made of.
+    ///
+    /// # Errors
+    /// Returns an error if Mercurial fails to list revisions.
+    pub async fn mutable(&self) -> Result<Vec<Revision>> {
+        self.log(Some("not public() and not obsolete()")).await
+    }
+
+    /// Move the revisions orphaned by pruning any of `revs` onto the
+    /// successors of their pruned ancestors, or onto their nearest surviving
+    /// ancestors.
+    ///
+    /// # Errors
+    /// Returns an error if Mercurial fails to restack the revisions, for
+    /// example because of a conflict.
+    pub async fn restack(&self, revs: &[&str]) -> Result<()> {
+        let revset = format!("orphan() and descendants({})", revs.join(" + "));
+        self.run_command(vec!["evolve", "--rev", &revset]).await?;
+        Ok(())
+    }
+
+    /// Bring back pruned revisions, as new revisions with the same contents.
+    /// Revisions that were pruned with a successor should be revived as
+    /// `duplicate`s, which aren't marked as succeeding them, so they don't
+    /// diverge from that successor. Revisions in `revs` stay on their revived
+    /// parents if those are in `revs` too.
+    ///
+    /// # Errors
+    /// Returns an error if Mercurial fails to revive the revisions.
+    pub async fn revive(&self, revs: &[&str], duplicate: bool) -> Result<()> {
+        let mut args = vec!["touch", "--hidden"];
+        for rev in revs {
+            args.push("--rev");
+            args.push(rev);
+        }
+        if duplicate {
+            args.push("--duplicate");
         }
         self.run_command(args).await?;
         Ok(())
     }
+
+    /// Prune several revisions, each paired with an optional successor, in as
+    /// few commands as possible, marking them as obsolete. The results of each
+    /// command are yielded as soon as it finishes, paired with the position in
+    /// `prunes` of the revision they belong to.
+    ///
+    /// A failure only affects the revisions it happened to, and the rest are
+    /// still pruned.
+    pub fn prune_many<'a>(
+        &'a self,
+        prunes: &'a [(&'a str, Option<&'a str>)],
+    ) -> impl Stream<Item = Vec<(usize, Result<()>)>> + 'a {
+        stream::once(self.prune_batches(prunes))
+            .flat_map(stream::iter)
+            .then(move |batch| async move { self.prune_batch(&batch).await })
+    }
+
+    /// Split `prunes`, revisions each paired with an optional successor, into
+    /// batches that can each be pruned with a single command.
+    ///
+    /// If the revision numbers of the revisions and successors can't be
+    /// looked up, revisions with distinct successors are pruned one at a time.
+    async fn prune_batches<'a>(&self, prunes: &[(&'a str, Option<&'a str>)]) -> Vec<Batch<'a>> {
+        let hashes = prunes
+            .iter()
+            .flat_map(|&(rev, successor)| std::iter::once(rev).chain(successor));
+        let found = self.find_all(hashes).await.unwrap_or_default();
+        let numbers: Vec<(&str, u64)> = found
+            .iter()
+            .filter_map(|revision| Some((revision.hash.as_str(), revision.rev?)))
+            .collect();
+        batches(prunes, |hash| {
+            numbers
+                .iter()
+                .find(|(full, _)| full.starts_with(hash))
+                .map(|&(_, number)| number)
+        })
+    }
+
+    /// Prune the revisions in `batch`, returning the result for each of them
+    /// along with its position in the list the batch was made from.
+    ///
+    /// If the command fails, the revisions are pruned one at a time to find
+    /// out which of them can't be pruned.
+    async fn prune_batch(&self, batch: &Batch<'_>) -> Vec<(usize, Result<()>)> {
+        run_batch(batch, |args| async move {
+            self.run_command(args).await.map(drop)
+        })
+        .await
+    }
+}
+
+/// Revisions that can be pruned with a single command, either because they
+/// share a successor, or because each has a different one.
+#[derive(Debug, PartialEq, Eq)]
+struct Batch<'a> {
+    /// The position of each revision in the list the batch was made from.
+    indices: Vec<usize>,
+
+    /// The revisions to prune, in order of revision number.
+    revs: Vec<&'a str>,
+
+    /// Either no successors, a single successor shared by every revision, or
+    /// the successor of each revision in the same order as `revs`.
+    successors: Vec<&'a str>,
+}
+
+impl<'a> Batch<'a> {
+    /// Batch a single revision.
+    fn single(index: usize, rev: &'a str, successor: Option<&'a str>) -> Self {
+        Self {
+            indices: vec![index],
+            revs: vec![rev],
+            successors: successor.into_iter().collect(),
+        }
+    }
+
+    /// The arguments of the `hg prune` command that prunes the batch.
+    fn args(&self) -> Vec<&'a str> {
+        let mut args = vec!["prune"];
+        // Several revisions that landed as one changeset were folded, and
+        // several that landed separately are paired with their successors.
+        if self.revs.len() > 1 {
+            match self.successors.len() {
+                0 => {}
+                1 => args.push("--fold"),
+                _ => args.push("--biject"),
+            }
+        }
+        for rev in &self.revs {
+            args.push("--rev");
+            args.push(rev);
+        }
+        for successor in &self.successors {
+            args.push("--succ");
+            args.push(successor);
+        }
+        args
+    }
+}
+
+/// Split `prunes` into batches that can each be pruned with a single command:
+/// one for the revisions without a successor, one for each successor shared by
+/// several revisions, and as few as possible for revisions with distinct
+/// successors.
+///
+/// `hg prune --biject` pairs revisions with successors in order of revision
+/// number, whatever order they are given in. So revisions with distinct
+/// successors are only batched together when their successors are in the same
+/// order, which is usual since stacks land in order. `number` looks up the
+/// revision number of a hash, and revisions without one are pruned alone.
+fn batches<'a, F>(prunes: &[(&'a str, Option<&'a str>)], number: F) -> Vec<Batch<'a>>
+where
+    F: Fn(&str) -> Option<u64>,
+{
+    let mut no_successor = Batch {
+        indices: Vec::new(),
+        revs: Vec::new(),
+        successors: Vec::new(),
+    };
+    let mut shared: Vec<(&str, Vec<usize>)> = Vec::new();
+    for (index, &(rev, successor)) in prunes.iter().enumerate() {
+        match successor {
+            None => {
+                no_successor.indices.push(index);
+                no_successor.revs.push(rev);
+            }
+            Some(successor) => match shared.iter_mut().find(|(other, _)| *other == successor) {
+                Some((_, indices)) => indices.push(index),
+                None => shared.push((successor, vec![index])),
+            },
+        }
+    }
+
+    let mut batches = Vec::new();
+    if !no_successor.indices.is_empty() {
+        batches.push(no_successor);
+    }
+
+    let mut distinct: Vec<(u64, u64, usize)> = Vec::new();
+    for (successor, indices) in shared {
+        let numbers = (number(prunes[indices[0]].0), number(successor));
+        match (indices.as_slice(), numbers) {
+            (&[index], (Some(rev), Some(succ))) => distinct.push((rev, succ, index)),
+            (&[index], _) => batches.push(Batch::single(index, prunes[index].0, Some(successor))),
+            _ => batches.push(Batch {
+                revs: indices.iter().map(|&index| prunes[index].0).collect(),
+                indices,
+                successors: vec![successor],
+            }),
+        }
+    }
+
+    // Add each revision to the first batch whose successors it can follow
+    distinct.sort_unstable();
+    let mut bijections: Vec<(u64, Batch)> = Vec::new();
+    for (_, succ, index) in distinct {
+        let (rev, successor) = prunes[index];
+        match bijections.iter_mut().find(|(last, _)| *last < succ) {
+            Some((last, batch)) => {
+                *last = succ;
+                batch.indices.push(index);
+                batch.revs.push(rev);
+                batch.successors.extend(successor);
+            }
+            None => bijections.push((succ, Batch::single(index, rev, successor))),
+        }
+    }
+    batches.extend(bijections.into_iter().map(|(_, batch)| batch));
+    batches
+}
+
+/// Prune `batch` by running `hg` with the arguments given to `run`, falling
+/// back to pruning one revision at a time if that fails.
+async fn run_batch<'a, F, Fut>(batch: &Batch<'a>, run: F) -> Vec<(usize, Result<()>)>
+where
+    F: Fn(Vec<&'a str>) -> Fut,
+    Fut: std::future::Future<Output = Result<()>>,
+{
+    match run(batch.args()).await {
+        Ok(()) => batch.indices.iter().map(|&index| (index, Ok(()))).collect(),
+        Err(err) if batch.revs.len() == 1 => vec![(batch.indices[0], Err(err))],
+        Err(_) => {
+            let mut results = Vec::new();
+            for (position, (&index, &rev)) in batch.indices.iter().zip(&batch.revs).enumerate() {
+                let successor = match batch.successors.len() {
+                    0 => None,
+                    1 => Some(batch.successors[0]),
+                    _ => Some(batch.successors[position]),
+                };
+                let single = Batch::single(index, rev, successor);
+                results.push((index, run(single.args()).await));
+            }
+            results
+        }
+    }
 }
 
-/// A revision in a Mercurial repository.
+/// Find the `.hg` directory of the repository containing `path`.
+#[must_use]
+pub fn hg_dir(path: &Path) -> Option<std::path::PathBuf> {
+    path.canonicalize().ok().and_then(|path| {
+        path.ancestors()
+            .map(|dir| dir.join(".hg"))
+            .find(|dir| dir.is_dir())
+    })
+}
+
+/// A revision in a Mercurial repository. Anything other than the description
+/// and hash may be left out, and is then empty.
 #[derive(Debug, Deserialize)]
 pub struct Revision {
     /// The entire body of the revision comment
@@ -132,6 +610,141 @@ pub struct Revision {
     /// The global identifying hash of the revision.
     #[serde(rename = "node")]
     pub hash: String,
+
+    /// The revision's number, which is only meaningful in this repository.
+    #[serde(default)]
+    pub rev: Option<u64>,
+
+    /// The hashes of the revision's parents. Root revisions have none, and
+    /// merges have two.
+    #[serde(default, deserialize_with = "non_null_nodes")]
+    pub parents: Vec<String>,
+
+    /// The revision's phase.
+    #[serde(default)]
+    pub phase: Phase,
+
+    /// The named branch the revision is on.
+    #[serde(default = "default_branch")]
+    pub branch: String,
+
+    /// The bookmarks pointing at the revision.
+    #[serde(default)]
+    pub bookmarks: Vec<String>,
+
+    /// The tags pointing at the revision.
+    #[serde(default)]
+    pub tags: Vec<String>,
+
+    /// The topic the revision belongs to, if the topic extension is in use.
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub topic: Option<String>,
+
+    /// Who committed the revision, usually as `Name <email>`.
+    #[serde(default)]
+    pub user: String,
+
+    /// When the revision was committed.
+    #[serde(default)]
+    pub date: Option<Date>,
+
+    /// Whether the revision has been obsoleted, such as by being pruned.
+    #[serde(default, deserialize_with = "non_empty")]
+    pub obsolete: bool,
+
+    /// Problems caused by the revision's ancestors being rewritten.
+    #[serde(default)]
+    pub instabilities: Vec<Instability>,
+}
+
+/// The phase of a revision, which controls whether it can be rewritten.
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    /// The revision has been published, and can't be rewritten.
+    Public,
+
+    /// The revision hasn't been published yet, and can be rewritten.
+    Draft,
+
+    /// The revision won't be pushed or pulled.
+    Secret,
+}
+
+impl Default for Phase {
+    /// Revisions being pruned are drafts, so that's assumed when the phase
+    /// is unknown.
+    fn default() -> Self {
+        Self::Draft
+    }
+}
+
+/// When a revision was committed.
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(try_from = "(f64, i32)")]
+pub struct Date {
+    /// The number of seconds since the Unix epoch.
+    pub timestamp: i64,
+
+    /// The committer's offset from UTC in seconds, with positive offsets
+    /// being west of UTC as Mercurial records them.
+    pub offset: i32,
+}
+
+impl TryFrom<(f64, i32)> for Date {
+    type Error = String;
+
+    #[allow(clippy::cast_possible_truncation)] // Whole seconds are enough, and the range is checked.
+    fn try_from((timestamp, offset): (f64, i32)) -> std::result::Result<Self, Self::Error> {
+        #[allow(clippy::cast_precision_loss)] // The bounds only need to be approximately right.
+        let range = (i64::MIN as f64)..(i64::MAX as f64);
+        if range.contains(&timestamp) {
+            Ok(Self {
+                timestamp: timestamp.floor() as i64,
+                offset,
+            })
+        } else {
+            Err(format!("timestamp {timestamp} is out of range"))
+        }
+    }
+}
+
+/// A problem with a revision caused by history being rewritten.
+#[derive(Copy, Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Instability {
+    /// An ancestor of the revision is obsolete.
+    Orphan,
+
+    /// The revision tries to rewrite a public revision.
+    PhaseDivergent,
+
+    /// The revision and another are both successors of the same revision.
+    ContentDivergent,
+}
+
+#[cfg(test)]
+impl Revision {
+    /// Make a draft revision with only a hash and a description, for tests to
+    /// fill in further.
+    #[must_use]
+    pub fn new(hash: &str, description: &str) -> Self {
+        Self {
+            description: description.to_string(),
+            hash: hash.to_string(),
+            rev: None,
+            parents: Vec::new(),
+            phase: Phase::default(),
+            branch: default_branch(),
+            bookmarks: Vec::new(),
+            tags: Vec::new(),
+            topic: None,
+            user: String::new(),
+            date: None,
+            obsolete: false,
+            instabilities: Vec::new(),
+        }
+    }
 }
 
 impl Revision {
@@ -142,15 +755,469 @@ impl Revision {
         parts.next()
     }
 
-    /// Get the bug listed in the revision subject, if any.
+    /// Get the ID of the Phabricator revision this revision was submitted as,
+    /// from a `Differential Revision: https://phabricator.services.mozilla.com/D123`
+    /// line in the description. The `D` prefix is not included.
+    #[must_use]
+    pub fn differential(&self) -> Option<u64> {
+        self.description.lines().rev().find_map(differential_id)
+    }
+
+    /// Get the first bug listed in the revision subject, if any.
     #[must_use]
     pub fn bug(&self) -> Option<Bug> {
-        let mut words = self.subject()?.split_whitespace();
-        let first = words.next()?;
-        if first.to_lowercase() == "bug" {
-            words.next().map(|second| Bug::new(second.to_string()))
+        self.bugs().into_iter().next()
+    }
+
+    /// Whether an ancestor of the revision is obsolete, leaving it orphaned.
+    #[must_use]
+    pub fn is_orphan(&self) -> bool {
+        self.instabilities.contains(&Instability::Orphan)
+    }
+
+    /// Get every bug listed in the revision subject, in the order they are mentioned.
+    #[must_use]
+    pub fn bugs(&self) -> Vec<Bug> {
+        self.subject()
+            .map(bug_ids)
+            .unwrap_or_default()
+            .into_iter()
+            .map(Bug::new)
+            .collect()
+    }
+}
+
+/// The branch that revisions are on unless they're given another one.
+fn default_branch() -> String {
+    "default".to_string()
+}
+
+/// Mercurial uses a hash of all zeros for missing parents. Leave those out.
+fn non_null_nodes<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let nodes = Vec::<String>::deserialize(deserializer)?;
+    Ok(nodes
+        .into_iter()
+        .filter(|node| node.bytes().any(|b| b != b'0'))
+        .collect())
+}
+
+/// Templates print missing values as an empty string. Treat those as `None`.
+fn empty_as_none<'de, D>(deserializer: D) -> std::result::Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<String>::deserialize(deserializer)?.filter(|value| !value.is_empty()))
+}
+
+/// Templates print flags, such as `obsolete`, as their name when set and an
+/// empty string otherwise.
+fn non_empty<'de, D>(deserializer: D) -> std::result::Result<bool, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(!String::deserialize(deserializer)?.is_empty())
+}
+
+/// Build the revset used by `Hg::find_public`, if `revision` mentions any bugs
+/// or a Differential Revision to search for. Numbers must match whole, so that
+/// bug 1234 doesn't find changesets for bug 12345, but may follow letters, as
+/// in `bug1234`.
+fn landed_revset(revision: &Revision) -> Option<String> {
+    let mut patterns: Vec<String> = revision
+        .bugs()
+        .iter()
+        .map(|bug| format!(r"(?<!\d){}\b", bug.id))
+        .collect();
+    patterns.extend(revision.differential().map(|id| format!(r"/D{id}\b")));
+    if patterns.is_empty() {
+        return None;
+    }
+
+    let patterns: Vec<String> = patterns
+        .iter()
+        .map(|pattern| format!("desc(r're:{pattern}')"))
+        .collect();
+    Some(format!(
+        "public() and descendants(max(::{} and public())) and ({})",
+        revision.hash,
+        patterns.join(" or ")
+    ))
+}
+
+/// Find the bug numbers in a commit subject, following the conventions used
+/// for mozilla-central commit messages. Bugs can be mentioned as `Bug 123`,
+/// `bug #123`, `bug123`, or `b=123`, or as a number at the very start of the
+/// subject followed by a separator, as in `123 - ` or `123: `.
+fn bug_ids(subject: &str) -> Vec<u64> {
+    let lower = subject.to_lowercase();
+    let mut ids = Vec::new();
+
+    for (start, _) in lower.char_indices() {
+        let at_word_start = lower[..start]
+            .chars()
+            .next_back()
+            .is_none_or(|c| !is_word_char(c));
+        if !at_word_start {
+            continue;
+        }
+
+        let rest = &lower[start..];
+        let leading = start == 0 && rest.starts_with(|c: char| c.is_ascii_digit());
+        let number = if let Some(rest) = rest.strip_prefix("bug") {
+            let rest = rest.trim_start();
+            rest.strip_prefix('#').unwrap_or(rest)
+        } else if let Some(rest) = rest.strip_prefix("b=") {
+            rest
+        } else if leading {
+            rest
         } else {
-            None
+            continue;
+        };
+
+        let digits = number.len()
+            - number
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .len();
+        let after = &number[digits..];
+        let at_word_end = after.chars().next().is_none_or(|c| !is_word_char(c));
+        // A bare number is only a bug when separated from the rest of the
+        // subject, so that "2 fixes for the toolbar" isn't bug 2
+        let separated = !leading || after.trim_start().starts_with(['-', ':']);
+        if digits > 0 && at_word_end && separated {
+            if let Ok(id) = number[..digits].parse() {
+                if !ids.contains(&id) {
+                    ids.push(id);
+                }
+            }
         }
     }
+
+    ids
+}
+
+/// Parse a `Differential Revision:` line from a description.
+fn differential_id(line: &str) -> Option<u64> {
+    let url = line.trim().strip_prefix(DIFFERENTIAL_PREFIX)?.trim();
+    url.trim_end_matches('/')
+        .rsplit('/')
+        .next()?
+        .strip_prefix('D')?
+        .parse()
+        .ok()
+}
+
+const fn is_word_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        batches, bug_ids, differential_id, landed_revset, run_batch, Batch, Date, Error,
+        Instability, Phase, Revision,
+    };
+    use futures::executor::block_on;
+
+    #[test]
+    fn bug_dash_subject() {
+        assert_eq!(
+            bug_ids("Bug 1234567 - Fix the thing r=mythmon"),
+            vec![1_234_567]
+        );
+    }
+
+    #[test]
+    fn bug_colon_subject() {
+        assert_eq!(bug_ids("Bug 123456: Fix the thing"), vec![123_456]);
+    }
+
+    #[test]
+    fn lowercase_and_hash() {
+        assert_eq!(bug_ids("bug #123456 - fix the thing"), vec![123_456]);
+        assert_eq!(bug_ids("bug123456 - fix the thing"), vec![123_456]);
+    }
+
+    #[test]
+    fn bug_in_parentheses() {
+        assert_eq!(bug_ids("Fix the thing (bug 123456)"), vec![123_456]);
+    }
+
+    #[test]
+    fn b_equals() {
+        assert_eq!(bug_ids("Fix the thing, b=123456, r=mythmon"), vec![123_456]);
+    }
+
+    #[test]
+    fn leading_number() {
+        assert_eq!(bug_ids("123456 - Fix the thing"), vec![123_456]);
+        assert_eq!(bug_ids("123456: Fix the thing"), vec![123_456]);
+    }
+
+    #[test]
+    fn backout() {
+        assert_eq!(
+            bug_ids("Backed out changeset 0a1b2c3d4e5f (bug 123456) for causing xpcshell failures"),
+            vec![123_456]
+        );
+        assert_eq!(
+            bug_ids("Backed out 2 changesets (bug 123456, bug 654321) for causing build bustages. CLOSED TREE"),
+            vec![123_456, 654_321]
+        );
+    }
+
+    #[test]
+    fn multiple_bugs() {
+        assert_eq!(
+            bug_ids("Bug 111111, bug 222222 - Share the parser r=mythmon"),
+            vec![111_111, 222_222]
+        );
+    }
+
+    #[test]
+    fn repeated_bug_is_listed_once() {
+        assert_eq!(
+            bug_ids("Bug 111111 - Follow up to bug 111111"),
+            vec![111_111]
+        );
+    }
+
+    #[test]
+    fn part_numbers_are_not_bugs() {
+        assert_eq!(bug_ids("Bug 123456 - Part 2: Fix the thing"), vec![123_456]);
+    }
+
+    #[test]
+    fn words_containing_bug_are_not_bugs() {
+        assert_eq!(bug_ids("Add debug 123 logging"), Vec::<u64>::new());
+        assert_eq!(bug_ids("Fix bugfix 123 regression"), Vec::<u64>::new());
+        assert_eq!(bug_ids("Remove bugs"), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn numbers_must_end_at_a_word_boundary() {
+        assert_eq!(bug_ids("Bug 123abc - Fix the thing"), Vec::<u64>::new());
+        assert_eq!(
+            bug_ids("1st attempt at fixing the thing"),
+            Vec::<u64>::new()
+        );
+    }
+
+    #[test]
+    fn no_bug() {
+        assert_eq!(bug_ids("No bug - Update the README"), Vec::<u64>::new());
+        assert_eq!(bug_ids("Bug - Fix the thing"), Vec::<u64>::new());
+        assert_eq!(bug_ids(""), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn leading_number_needs_separator() {
+        assert_eq!(bug_ids("2 fixes for the toolbar"), Vec::<u64>::new());
+        assert_eq!(bug_ids("123456"), Vec::<u64>::new());
+        assert_eq!(bug_ids("2 fixes for bug 123456"), vec![123_456]);
+    }
+
+    #[test]
+    fn oversized_numbers_are_ignored() {
+        assert_eq!(
+            bug_ids("Bug 99999999999999999999999 - Overflow"),
+            Vec::<u64>::new()
+        );
+    }
+
+    /// Revision numbers for the hashes used in prune tests, where `a1` is
+    /// revision 1.
+    fn number(hash: &str) -> Option<u64> {
+        hash.get(1..).and_then(|number| number.parse().ok())
+    }
+
+    #[test]
+    fn prune_batches() {
+        let prunes = [
+            ("d1", None),
+            ("d2", Some("s10")),
+            ("d3", Some("s20")),
+            ("d4", Some("s30")),
+            ("d5", None),
+            ("d6", Some("s40")),
+            ("d7", Some("s40")),
+        ];
+        let args: Vec<Vec<&str>> = batches(&prunes, number).iter().map(Batch::args).collect();
+        assert_eq!(
+            args,
+            vec![
+                vec!["prune", "--rev", "d1", "--rev", "d5"],
+                vec!["prune", "--fold", "--rev", "d6", "--rev", "d7", "--succ", "s40"],
+                vec![
+                    "prune", "--biject", "--rev", "d2", "--rev", "d3", "--rev", "d4", "--succ",
+                    "s10", "--succ", "s20", "--succ", "s30"
+                ],
+            ]
+        );
+    }
+
+    #[test]
+    fn prune_batches_follow_revision_order() {
+        // Given in any order, revisions are paired with successors in order
+        let prunes = [
+            ("d3", Some("s30")),
+            ("d1", Some("s10")),
+            ("d2", Some("s20")),
+        ];
+        let batched = batches(&prunes, number);
+        assert_eq!(batched.len(), 1);
+        assert_eq!(batched[0].indices, vec![1, 2, 0]);
+        assert_eq!(
+            batched[0].args(),
+            vec![
+                "prune", "--biject", "--rev", "d1", "--rev", "d2", "--rev", "d3", "--succ", "s10",
+                "--succ", "s20", "--succ", "s30"
+            ]
+        );
+
+        // Successors that landed in another order can't be paired in one command
+        let prunes = [
+            ("d1", Some("s20")),
+            ("d2", Some("s10")),
+            ("d3", Some("s30")),
+        ];
+        let args: Vec<Vec<&str>> = batches(&prunes, number).iter().map(Batch::args).collect();
+        assert_eq!(
+            args,
+            vec![
+                vec![
+                    "prune", "--biject", "--rev", "d1", "--rev", "d3", "--succ", "s20", "--succ",
+                    "s30"
+                ],
+                vec!["prune", "--rev", "d2", "--succ", "s10"],
+            ]
+        );
+
+        // Without revision numbers, revisions are pruned alone
+        let prunes = [("x", Some("s10")), ("d2", Some("s20"))];
+        assert_eq!(batches(&prunes, number).len(), 2);
+    }
+
+    #[test]
+    fn prune_batch_falls_back_to_single_revisions() {
+        let prunes = [
+            ("d1", Some("s10")),
+            ("d2", Some("s20")),
+            ("d3", Some("s30")),
+        ];
+        let batched = batches(&prunes, number);
+        let commands = std::cell::RefCell::new(Vec::new());
+        let results = block_on(run_batch(&batched[0], |args| {
+            commands.borrow_mut().push(args.clone());
+            async move {
+                if args.contains(&"d2") {
+                    Err(Error::Command {
+                        stdout: String::new(),
+                        stderr: "abort: cannot prune".to_string(),
+                    })
+                } else {
+                    Ok(())
+                }
+            }
+        }));
+
+        assert_eq!(commands.borrow().len(), 4);
+        assert_eq!(
+            commands.borrow()[2],
+            vec!["prune", "--rev", "d2", "--succ", "s20"]
+        );
+        let failed: Vec<usize> = results
+            .iter()
+            .filter(|(_, result)| result.is_err())
+            .map(|&(index, _)| index)
+            .collect();
+        assert_eq!(results.len(), 3);
+        assert_eq!(failed, vec![1]);
+    }
+
+    #[test]
+    fn landed_revsets() {
+        let revision = Revision::new(
+            "abcdef",
+            "Bug 1234 - Fix the thing\n\n\
+             Differential Revision: https://phabricator.services.mozilla.com/D56",
+        );
+        assert_eq!(
+            landed_revset(&revision).unwrap(),
+            concat!(
+                "public() and descendants(max(::abcdef and public())) and ",
+                r"(desc(r're:(?<!\d)1234\b') or desc(r're:/D56\b'))",
+            )
+        );
+
+        let revision = Revision::new("abcdef", "Fix the thing");
+        assert_eq!(landed_revset(&revision), None);
+    }
+
+    #[test]
+    fn differential_revision() {
+        assert_eq!(
+            differential_id(
+                "Differential Revision: https://phabricator.services.mozilla.com/D104001"
+            ),
+            Some(104_001)
+        );
+        assert_eq!(
+            differential_id("Differential Revision: https://phabricator.services.mozilla.com/D7/"),
+            Some(7)
+        );
+        assert_eq!(differential_id("Depends on D104001"), None);
+        assert_eq!(
+            differential_id("Differential Revision: https://phabricator.services.mozilla.com/"),
+            None
+        );
+    }
+
+    #[test]
+    fn template_output() {
+        let line = concat!(
+            r#"{"rev": 12, "node": "abcdef0123456789abcdef0123456789abcdef01", "#,
+            r#""branch": "default", "phase": "draft", "user": "A Dev <dev@example.com>", "#,
+            r#""date": [1614556800.0, -3600], "desc": "Bug 1 - Thing", "#,
+            r#""bookmarks": ["feature"], "tags": [], "#,
+            r#""parents": ["1111111111111111111111111111111111111111", "#,
+            r#""0000000000000000000000000000000000000000"], "#,
+            r#""topic": "", "obsolete": "", "instabilities": ["orphan"]}"#
+        );
+        let revision: Revision = serde_json::from_str(line).unwrap();
+        assert_eq!(revision.rev, Some(12));
+        assert_eq!(
+            revision.parents,
+            vec!["1111111111111111111111111111111111111111"]
+        );
+        assert_eq!(revision.phase, Phase::Draft);
+        assert_eq!(revision.bookmarks, vec!["feature"]);
+        assert_eq!(revision.topic, None);
+        assert_eq!(
+            revision.date,
+            Some(Date {
+                timestamp: 1_614_556_800,
+                offset: -3600
+            })
+        );
+        assert!(!revision.obsolete);
+        assert_eq!(revision.instabilities, vec![Instability::Orphan]);
+        assert!(revision.is_orphan());
+    }
+
+    #[test]
+    fn json_output() {
+        let revision: Revision = serde_json::from_str(
+            r#"{"rev": 3, "node": "abcdef0123456789abcdef0123456789abcdef01", "desc": "Thing",
+                "phase": "public", "topic": "stack", "obsolete": "obsolete"}"#,
+        )
+        .unwrap();
+        assert_eq!(revision.phase, Phase::Public);
+        assert_eq!(revision.branch, "default");
+        assert_eq!(revision.topic.as_deref(), Some("stack"));
+        assert!(revision.obsolete);
+        assert!(revision.parents.is_empty());
+    }
 }
@@ -0,0 +1,36 @@
+//! Settings shared by the HTTP clients for every web service that is queried.
+
+use std::time::Duration;
+
+/// Settings describing how to make requests to a web service.
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// The user agent to send with every request.
+    pub user_agent: String,
+
+    /// How long to wait for an entire request to complete.
+    pub timeout: Duration,
+
+    /// How long to wait for a connection to the server to be established.
+    pub connect_timeout: Duration,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            user_agent: concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION")).to_string(),
+            timeout: Duration::from_secs(30),
+            connect_timeout: Duration::from_secs(10),
+        }
+    }
+}
+
+impl Settings {
+    /// Start building an HTTP client that follows these settings.
+    pub fn client_builder(&self) -> reqwest::ClientBuilder {
+        reqwest::Client::builder()
+            .user_agent(&self.user_agent)
+            .timeout(self.timeout)
+            .connect_timeout(self.connect_timeout)
+    }
+}
@@ -0,0 +1,249 @@
+//! A journal of the revisions pruned in each session, so that a session can
+//! be undone.
+
+use async_std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+use thiserror::Error;
+
+/// The name of the journal file within a repository's `.hg` directory.
+const JOURNAL_FILE: &str = "hg-bz-prune.journal";
+
+/// The name of the file, next to the journal, holding the number of the
+/// latest session.
+const SESSION_FILE: &str = "hg-bz-prune.session";
+
+/// A problem that prevented the journal from being read or written.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// Could not read or write the journal
+    #[error("Could not access the journal")]
+    Io(#[from] io::Error),
+
+    /// The journal could not be parsed or serialized
+    #[error("The journal is not valid")]
+    Json(#[from] serde_json::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A revision that was pruned.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Entry {
+    /// The run of this tool that pruned the revision. Sessions are numbered
+    /// from 1, in the order they happened, and numbers are never reused.
+    pub session: u64,
+
+    /// The hash of the pruned revision.
+    pub hash: String,
+
+    /// The successor recorded for the revision, if any.
+    pub successor: Option<String>,
+
+    /// When the revision was pruned.
+    pub time: SystemTime,
+}
+
+/// The journal of a repository, stored in its `.hg` directory with one entry
+/// per line.
+#[derive(Debug)]
+pub struct Journal {
+    path: PathBuf,
+    session_path: PathBuf,
+}
+
+impl Journal {
+    /// Use the journal in the `.hg` directory `hg_dir`.
+    pub fn new<P: Into<PathBuf>>(hg_dir: P) -> Self {
+        let hg_dir = hg_dir.into();
+        Self {
+            path: hg_dir.join(JOURNAL_FILE),
+            session_path: hg_dir.join(SESSION_FILE),
+        }
+    }
+
+    /// Read every entry in the journal, oldest first. A journal that doesn't
+    /// exist yet is empty.
+    ///
+    /// # Errors
+    /// Returns an error if the journal can't be read or parsed.
+    pub async fn entries(&self) -> Result<Vec<Entry>> {
+        let contents = match fs::read_to_string(&self.path).await {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(serde_json::Deserializer::from_str(&contents)
+            .into_iter()
+            .collect::<std::result::Result<_, _>>()?)
+    }
+
+    /// Number a new session. Numbers are counted separately from the entries,
+    /// so that those of sessions that were undone aren't given out again.
+    ///
+    /// # Errors
+    /// Returns an error if the latest session number can't be read or written.
+    pub async fn new_session(&self) -> Result<u64> {
+        let last = match fs::read_to_string(&self.session_path).await {
+            Ok(contents) => serde_json::from_str(&contents)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => 0,
+            Err(err) => return Err(err.into()),
+        };
+        let session = last + 1;
+        write_atomically(&self.session_path, session.to_string()).await?;
+        Ok(session)
+    }
+
+    /// Add `entries` to the end of the journal.
+    ///
+    /// # Errors
+    /// Returns an error if the journal can't be read, parsed or written.
+    pub async fn record(&self, entries: &[Entry]) -> Result<()> {
+        let existing = self.entries().await?;
+        self.write(existing.iter().chain(entries)).await
+    }
+
+    /// Remove the entries of `session`, once it has been undone.
+    ///
+    /// # Errors
+    /// Returns an error if the journal can't be read, parsed or written.
+    pub async fn remove_session(&self, session: u64) -> Result<()> {
+        let entries = self.entries().await?;
+        self.write(entries.iter().filter(|entry| entry.session != session))
+            .await
+    }
+
+    /// Replace the journal with `entries`.
+    async fn write<'a, I>(&self, entries: I) -> Result<()>
+    where
+        I: Iterator<Item = &'a Entry> + Send,
+    {
+        let mut contents = String::new();
+        for entry in entries {
+            contents.push_str(&serde_json::to_string(entry)?);
+            contents.push('\n');
+        }
+        write_atomically(&self.path, contents).await
+    }
+}
+
+/// Replace the file at `path` with `contents`, without leaving it half written
+/// if interrupted.
+async fn write_atomically(path: &Path, contents: String) -> Result<()> {
+    let tmp = tmp_path(path);
+    fs::write(&tmp, contents).await?;
+    fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+/// The path to write the file at `path` to before it is moved into place.
+/// Each file gets its own, so that writing one never clobbers another.
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    tmp.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tmp_path, Entry, Journal, JOURNAL_FILE, SESSION_FILE};
+    use async_std::task;
+    use std::{fs, path::PathBuf, time::SystemTime};
+
+    /// A directory to keep a test's journal in, removed when the test ends.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir()
+                .join(format!("hg-bz-prune-journal-{}-{name}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn entry(session: u64, hash: &str, successor: Option<&str>) -> Entry {
+        Entry {
+            session,
+            hash: hash.to_string(),
+            successor: successor.map(ToString::to_string),
+            time: SystemTime::now(),
+        }
+    }
+
+    fn hashes(journal: &Journal) -> Vec<(u64, String)> {
+        task::block_on(journal.entries())
+            .unwrap()
+            .into_iter()
+            .map(|entry| (entry.session, entry.hash))
+            .collect()
+    }
+
+    #[test]
+    fn missing_journal_is_empty() {
+        let dir = TempDir::new("missing");
+        let journal = Journal::new(dir.0.as_path());
+        assert!(task::block_on(journal.entries()).unwrap().is_empty());
+        task::block_on(journal.remove_session(1)).unwrap();
+        assert!(hashes(&journal).is_empty());
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = TempDir::new("round-trip");
+        let journal = Journal::new(dir.0.as_path());
+        task::block_on(journal.record(&[entry(1, "a", Some("s")), entry(1, "b", None)])).unwrap();
+        task::block_on(journal.record(&[entry(2, "c", None)])).unwrap();
+
+        let entries = task::block_on(journal.entries()).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].successor.as_deref(), Some("s"));
+        assert_eq!(entries[1].successor, None);
+        assert_eq!(
+            hashes(&journal),
+            vec![
+                (1, "a".to_string()),
+                (1, "b".to_string()),
+                (2, "c".to_string())
+            ]
+        );
+
+        task::block_on(journal.remove_session(1)).unwrap();
+        assert_eq!(hashes(&journal), vec![(2, "c".to_string())]);
+    }
+
+    #[test]
+    fn sessions_are_never_reused() {
+        let dir = TempDir::new("sessions");
+        let journal = Journal::new(dir.0.as_path());
+        assert_eq!(task::block_on(journal.new_session()).unwrap(), 1);
+        task::block_on(journal.record(&[entry(1, "a", None)])).unwrap();
+        assert_eq!(task::block_on(journal.new_session()).unwrap(), 2);
+        task::block_on(journal.record(&[entry(2, "b", None)])).unwrap();
+
+        task::block_on(journal.remove_session(2)).unwrap();
+        assert_eq!(task::block_on(journal.new_session()).unwrap(), 3);
+    }
+
+    #[test]
+    fn tmp_paths_are_distinct() {
+        let dir = async_std::path::Path::new(".hg");
+        assert_eq!(
+            tmp_path(&dir.join(JOURNAL_FILE)),
+            dir.join("hg-bz-prune.journal.tmp")
+        );
+        assert_eq!(
+            tmp_path(&dir.join(SESSION_FILE)),
+            dir.join("hg-bz-prune.session.tmp")
+        );
+    }
+}
@@ -0,0 +1,1006 @@
+//! Detection of landed changesets mentioned in Bugzilla comments.
+
+use crate::{bz::Comment, hg::Revision};
+use serde::Serialize;
+
+/// The host that Mozilla's Mercurial repositories are served from.
+const HG_HOST: &str = "hg.mozilla.org/";
+
+/// The repository that revisions must land in to be considered merged, unless
+/// other repositories are configured.
+pub const MOZILLA_CENTRAL: &str = "mozilla-central";
+
+/// The repository recorded for landings found among the public changesets of
+/// the local repository.
+pub const LOCAL: &str = "local";
+
+/// The shortest abbreviated hash that is accepted as identifying a changeset.
+const MIN_HASH_LEN: usize = 12;
+
+/// The length of a full changeset hash.
+const MAX_HASH_LEN: usize = 40;
+
+/// How comments that announce a push to a repository begin.
+const PUSH_COMMENT_PREFIX: &str = "Pushed by ";
+
+/// How lines that describe a backout begin, in lower case.
+const BACKOUT_PREFIXES: &[&str] = &["backed out ", "backout by "];
+
+/// Words in a commit subject that start a list of reviewers or approvers.
+const REVIEW_FLAGS: &[&str] = &["r=", "r?", "r+", "sr=", "rs=", "a="];
+
+/// A changeset that a comment says has landed in a repository.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Landing {
+    /// The path of the repository on hg.mozilla.org, such as
+    /// `mozilla-central` or `integration/autoland`, or `local` for public
+    /// changesets found in the local repository.
+    pub repository: String,
+
+    /// The hash of the landed changeset. This may be abbreviated.
+    pub hash: String,
+
+    /// The first line of the landed changeset's description, if the comment included it.
+    pub summary: Option<String>,
+}
+
+/// A landing that has been identified as the successor of a draft revision.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Successor {
+    /// The landed changeset.
+    pub landing: Landing,
+
+    /// How the landing was matched to the revision.
+    pub matched_by: Match,
+}
+
+/// The evidence used to match a landing to a draft revision. Variants are
+/// ordered from the least to the most confident.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Match {
+    /// The landed changeset is the only one on the revision's bug.
+    OnlyLanding,
+
+    /// The landed changeset has the same subject as the revision.
+    Subject,
+
+    /// The landed changeset has the same Differential Revision as the revision.
+    Differential,
+}
+
+impl std::fmt::Display for Match {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Differential => write!(f, "differential revision matches"),
+            Self::Subject => write!(f, "subject matches"),
+            Self::OnlyLanding => write!(f, "only landing on the bug"),
+        }
+    }
+}
+
+/// Why no successor could be found for a draft revision.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NoSuccessor {
+    /// No landing in an accepted repository matches the revision.
+    NoLanding,
+
+    /// The revision landed, but was backed out and has not landed again since.
+    BackedOut,
+}
+
+/// What a comment announcing a backout says was backed out.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Backout {
+    /// The changesets with these hashes, which may be abbreviated.
+    Changesets(Vec<String>),
+
+    /// The comment doesn't say which changesets were backed out, such as
+    /// "Backed out 2 changesets (bug 123)", so every changeset that landed
+    /// before it is assumed to have been.
+    Earlier,
+}
+
+/// How much evidence is needed to accept a successor without asking.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Policy {
+    /// Accept any successor that was found.
+    Any,
+
+    /// Only accept successors whose Differential Revision or subject matches
+    /// the revision.
+    Subject,
+}
+
+impl Policy {
+    /// Whether a successor matched in the given way satisfies this policy.
+    #[must_use]
+    pub const fn accepts(self, matched_by: Match) -> bool {
+        match self {
+            Self::Any => true,
+            Self::Subject => matches!(matched_by, Match::Differential | Match::Subject),
+        }
+    }
+}
+
+impl std::str::FromStr for Policy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "any" => Ok(Self::Any),
+            "subject" => Ok(Self::Subject),
+            _ => Err(format!(
+                "unknown policy {s:?}, expected \"any\" or \"subject\""
+            )),
+        }
+    }
+}
+
+impl Landing {
+    /// Whether `other` refers to the same changeset, accounting for abbreviated hashes.
+    #[must_use]
+    pub fn same_changeset(&self, other: &Self) -> bool {
+        same_hash(&self.hash, &other.hash)
+    }
+}
+
+/// Find every landing mentioned in the text of a comment, in the order they appear.
+///
+/// Comments announcing a push list each changeset's URL on its own line,
+/// followed by the first line of its description. That line is kept as the
+/// landing's summary.
+#[must_use]
+pub fn parse(text: &str) -> Vec<Landing> {
+    let is_push = text.starts_with(PUSH_COMMENT_PREFIX);
+    let mut landings = Vec::new();
+    let mut lines = text.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        let mut found = parse_line(line);
+        if let (true, [landing]) = (is_push, found.as_mut_slice()) {
+            if let Some(next) = lines.peek().filter(|next| parse_line(next).is_empty()) {
+                landing.summary = Some(next.trim().to_string()).filter(|s| !s.is_empty());
+                lines.next();
+            }
+        }
+        landings.extend(found);
+    }
+
+    landings
+}
+
+/// Find out whether a comment announces a backout, and what was backed out.
+/// Any landings mentioned in such a comment are the backout changesets
+/// themselves, rather than landings of the bug's patches.
+#[must_use]
+pub fn parse_backout(text: &str) -> Option<Backout> {
+    let lines: Vec<String> = text
+        .lines()
+        .map(|line| line.trim().to_lowercase())
+        .filter(|line| {
+            BACKOUT_PREFIXES
+                .iter()
+                .any(|prefix| line.starts_with(prefix))
+        })
+        .collect();
+    if lines.is_empty() {
+        return None;
+    }
+
+    let hashes: Vec<String> = lines
+        .iter()
+        .filter(|line| line.starts_with(BACKOUT_PREFIXES[0]))
+        .flat_map(|line| line.split_whitespace())
+        .filter(|word| !word.contains(HG_HOST) && !word.contains("://"))
+        .flat_map(|word| word.split(|c: char| !c.is_ascii_alphanumeric()))
+        .filter(|word| is_hash(word))
+        .map(ToString::to_string)
+        .collect();
+
+    if hashes.is_empty() {
+        Some(Backout::Earlier)
+    } else {
+        Some(Backout::Changesets(hashes))
+    }
+}
+
+/// Find the landings in any of `repositories` mentioned in `comments`, oldest
+/// first, leaving out backout changesets.
+#[must_use]
+pub fn accepted_landings(comments: &[Comment], repositories: &[&str]) -> Vec<Landing> {
+    History::new(comments, repositories).candidates
+}
+
+/// Find the landing in one of `repositories` that corresponds to `revision`,
+/// among the landings mentioned in `comments`.
+///
+/// If the revision has a Differential Revision, and one of the landed
+/// changesets in `landed` has the same one, that landing matches. Otherwise a
+/// landing matches if its summary, or the summary of the same changeset pushed
+/// to another repository, has the same subject as the revision. If no landing
+/// has a known summary, and only one changeset landed, it is assumed to be the
+/// revision's. Later landings are preferred over earlier ones.
+///
+/// Backout changesets are never considered landings, and landings that were
+/// backed out can't be successors. If the revision's latest landing was backed
+/// out, the revision has no successor until it lands again.
+///
+/// # Errors
+/// Returns why no successor was found, if there isn't one.
+pub fn find_successor(
+    revision: &Revision,
+    comments: &[Comment],
+    repositories: &[&str],
+    landed: &[Revision],
+) -> Result<Successor, NoSuccessor> {
+    let history = History::new(comments, repositories);
+
+    if let Some(differential) = revision.differential() {
+        if let Some(found) = history.candidates.iter().rev().find(|candidate| {
+            landed.iter().any(|landed| {
+                same_hash(&landed.hash, &candidate.hash)
+                    && landed.differential() == Some(differential)
+            })
+        }) {
+            return history.successor(found, Match::Differential);
+        }
+    }
+
+    let subject = revision.subject().map(normalize_subject);
+    if let Some(found) = history.candidates.iter().rev().find(|candidate| {
+        subject.is_some() && candidate.summary.as_deref().map(normalize_subject) == subject
+    }) {
+        return history.successor(found, Match::Subject);
+    }
+
+    let surviving: Vec<&Landing> = history
+        .candidates
+        .iter()
+        .filter(|candidate| history.survives(candidate))
+        .collect();
+    match surviving.as_slice() {
+        [only] if only.summary.is_none() => history.successor(only, Match::OnlyLanding),
+        [] if !history.candidates.is_empty() => Err(NoSuccessor::BackedOut),
+        _ => Err(NoSuccessor::NoLanding),
+    }
+}
+
+/// Find the public changeset that corresponds to `revision`, among the
+/// changesets in `public`, which should be in the order they were added to the
+/// repository.
+///
+/// A changeset matches if it has the same Differential Revision as the
+/// revision, or failing that, if it mentions the same bug and has the same
+/// subject. Later changesets are preferred over earlier ones.
+///
+/// # Errors
+/// Returns why no successor was found, if there isn't one. If the matching
+/// changeset was backed out by a later changeset in `public`, it isn't a
+/// successor.
+pub fn find_local_successor(
+    revision: &Revision,
+    public: &[Revision],
+) -> Result<Successor, NoSuccessor> {
+    let bugs: Vec<u64> = revision.bugs().iter().map(|bug| bug.id).collect();
+    let subject = revision
+        .subject()
+        .map(normalize_subject)
+        .filter(|subject| !subject.is_empty());
+
+    let by_differential = revision.differential().and_then(|differential| {
+        public
+            .iter()
+            .rposition(|changeset| changeset.differential() == Some(differential))
+            .map(|index| (index, Match::Differential))
+    });
+    let by_subject = || {
+        public
+            .iter()
+            .rposition(|changeset| {
+                subject.is_some()
+                    && changeset.subject().map(normalize_subject) == subject
+                    && changeset.bugs().iter().any(|bug| bugs.contains(&bug.id))
+            })
+            .map(|index| (index, Match::Subject))
+    };
+    let Some((index, matched_by)) = by_differential.or_else(by_subject) else {
+        return Err(NoSuccessor::NoLanding);
+    };
+
+    let found = &public[index];
+    if is_backed_out(found, &public[index + 1..], &bugs) {
+        return Err(NoSuccessor::BackedOut);
+    }
+
+    Ok(Successor {
+        landing: Landing {
+            repository: LOCAL.to_string(),
+            hash: found.hash.clone(),
+            summary: found.subject().map(ToString::to_string),
+        },
+        matched_by,
+    })
+}
+
+/// Whether any of the changesets in `later`, which landed after `landed`, back
+/// it out.
+///
+/// Backouts that don't say which changesets they backed out are
+/// assumed to include `landed` if they mention any of `bugs`.
+#[must_use]
+pub fn is_backed_out(landed: &Revision, later: &[Revision], bugs: &[u64]) -> bool {
+    later
+        .iter()
+        .any(|changeset| match parse_backout(&changeset.description) {
+            Some(Backout::Changesets(hashes)) => {
+                hashes.iter().any(|hash| same_hash(hash, &landed.hash))
+            }
+            Some(Backout::Earlier) => changeset.bugs().iter().any(|bug| bugs.contains(&bug.id)),
+            None => false,
+        })
+}
+
+/// What the comments on a bug say has landed for it, and what was backed out.
+struct History {
+    /// The changesets that landed in accepted repositories, oldest first. Summaries
+    /// from pushes of the same changeset to other repositories are included.
+    candidates: Vec<Landing>,
+
+    /// The hashes of changesets that were backed out.
+    backed_out: Vec<String>,
+}
+
+impl History {
+    fn new(comments: &[Comment], repositories: &[&str]) -> Self {
+        let mut landings: Vec<Landing> = Vec::new();
+        let mut backouts: Vec<Landing> = Vec::new();
+        let mut backed_out: Vec<String> = Vec::new();
+        for comment in comments {
+            let found = parse(&comment.raw_text);
+            match parse_backout(&comment.raw_text) {
+                None => landings.extend(found),
+                Some(Backout::Changesets(hashes)) => {
+                    backed_out.extend(hashes);
+                    backouts.extend(found);
+                }
+                Some(Backout::Earlier) => {
+                    backed_out.extend(landings.iter().map(|landing| landing.hash.clone()));
+                    backouts.extend(found);
+                }
+            }
+        }
+        // Backouts are merged to other repositories like any other changeset.
+        landings.retain(|landing| !backouts.iter().any(|b| b.same_changeset(landing)));
+
+        let mut candidates: Vec<Landing> = Vec::new();
+        for landing in landings
+            .iter()
+            .filter(|l| repositories.contains(&l.repository.as_str()))
+        {
+            candidates.retain(|candidate| !candidate.same_changeset(landing));
+            let summary = landings
+                .iter()
+                .filter(|other| other.same_changeset(landing))
+                .find_map(|other| other.summary.clone());
+            candidates.push(Landing {
+                summary,
+                ..landing.clone()
+            });
+        }
+
+        Self {
+            candidates,
+            backed_out,
+        }
+    }
+
+    fn survives(&self, landing: &Landing) -> bool {
+        !self
+            .backed_out
+            .iter()
+            .any(|hash| same_hash(&landing.hash, hash))
+    }
+
+    /// Accept `landing` as a successor, unless it was backed out.
+    fn successor(&self, landing: &Landing, matched_by: Match) -> Result<Successor, NoSuccessor> {
+        if self.survives(landing) {
+            Ok(Successor {
+                landing: landing.clone(),
+                matched_by,
+            })
+        } else {
+            Err(NoSuccessor::BackedOut)
+        }
+    }
+}
+
+/// Reduce a commit subject to a form that can be compared with other
+/// subjects for the same change. The bug number prefix, which pushes often
+/// leave out, and the list of reviewers, which can change at landing time, are
+/// removed.
+fn normalize_subject(subject: &str) -> String {
+    let mut words: Vec<&str> = subject.split_whitespace().collect();
+
+    if words.first().map(|w| w.to_lowercase()) == Some("bug".to_string()) && words.len() > 1 {
+        words.drain(..2);
+        if matches!(words.first(), Some(&"-" | &":" | &"--")) {
+            words.remove(0);
+        }
+    }
+
+    if let Some(end) = words.iter().position(|word| {
+        let word = word.trim_start_matches('(');
+        REVIEW_FLAGS.iter().any(|flag| word.starts_with(flag))
+    }) {
+        words.truncate(end);
+    }
+
+    words
+        .join(" ")
+        .trim_end_matches(['.', ',', ';'])
+        .to_lowercase()
+}
+
+/// Find every landing mentioned in a single line of a comment.
+fn parse_line(line: &str) -> Vec<Landing> {
+    line.split(|c: char| c.is_whitespace() || "<>()[]\"',".contains(c))
+        .filter_map(|word| {
+            word.find(HG_HOST)
+                .map(|start| &word[start + HG_HOST.len()..])
+        })
+        .filter_map(parse_path)
+        .collect()
+}
+
+/// Parse the part of a URL after the host, such as
+/// `integration/autoland/rev/0123456789ab` or
+/// `mozilla-central/pushloghtml?changeset=0123456789ab`.
+fn parse_path(path: &str) -> Option<Landing> {
+    if let Some((repository, rest)) = path.split_once("/rev/") {
+        let hash = rest.split(['/', '#', '?']).next()?;
+        return landing(repository, hash);
+    }
+
+    let (repository, query) = path
+        .split_once("/pushloghtml?")
+        .or_else(|| path.split_once("/pushlog?"))?;
+    let params: Vec<(&str, &str)> = query
+        .split(['&', '#'])
+        .filter_map(|param| param.split_once('='))
+        .collect();
+    ["changeset", "tochange"]
+        .iter()
+        .find_map(|key| params.iter().find(|(name, _)| name == key))
+        .and_then(|(_, hash)| landing(repository, hash))
+}
+
+fn landing(repository: &str, hash: &str) -> Option<Landing> {
+    let hash = hash.trim_end_matches('.');
+    if is_hash(hash) && !repository.is_empty() {
+        Some(Landing {
+            repository: repository.trim_matches('/').to_string(),
+            hash: hash.to_lowercase(),
+            summary: None,
+        })
+    } else {
+        None
+    }
+}
+
+/// Whether `word` looks like a changeset hash, possibly abbreviated.
+#[must_use]
+pub fn is_hash(word: &str) -> bool {
+    (MIN_HASH_LEN..=MAX_HASH_LEN).contains(&word.len())
+        && word.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Whether two hashes refer to the same changeset, accounting for abbreviation.
+fn same_hash(a: &str, b: &str) -> bool {
+    a.starts_with(b) || b.starts_with(a)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        find_local_successor, find_successor, parse, parse_backout, Backout, Landing, Match,
+        NoSuccessor, Successor, MOZILLA_CENTRAL,
+    };
+    use crate::{bz::Comment, hg::Revision};
+
+    fn landing(repository: &str, hash: &str) -> Landing {
+        Landing {
+            repository: repository.to_string(),
+            hash: hash.to_string(),
+            summary: None,
+        }
+    }
+
+    fn summarized(repository: &str, hash: &str, summary: &str) -> Landing {
+        Landing {
+            summary: Some(summary.to_string()),
+            ..landing(repository, hash)
+        }
+    }
+
+    fn comments(texts: &[&str]) -> Vec<Comment> {
+        texts
+            .iter()
+            .zip(1..)
+            .map(|(text, id)| Comment {
+                id,
+                raw_text: (*text).to_string(),
+                creation_time: format!("2021-03-{id:02}T12:00:00Z"),
+            })
+            .collect()
+    }
+
+    fn revision(description: &str) -> Revision {
+        Revision::new("0123456789abcdef0123456789abcdef01234567", description)
+    }
+
+    #[test]
+    fn merge_comment() {
+        let text = "https://hg.mozilla.org/mozilla-central/rev/4b2a7b2d3e8e";
+        assert_eq!(
+            parse(text),
+            vec![landing("mozilla-central", "4b2a7b2d3e8e")]
+        );
+    }
+
+    #[test]
+    fn multiple_merges() {
+        let text = "https://hg.mozilla.org/mozilla-central/rev/8a1d5bd2d4c5\n\
+                    https://hg.mozilla.org/mozilla-central/rev/9c3ab7e4e0a1\n\
+                    https://hg.mozilla.org/mozilla-central/rev/f00dfa11c0de";
+        assert_eq!(
+            parse(text),
+            vec![
+                landing("mozilla-central", "8a1d5bd2d4c5"),
+                landing("mozilla-central", "9c3ab7e4e0a1"),
+                landing("mozilla-central", "f00dfa11c0de"),
+            ]
+        );
+    }
+
+    #[test]
+    fn autoland_push() {
+        let text = "Pushed by mcooper@mozilla.com:\n\
+                    https://hg.mozilla.org/integration/autoland/rev/0b5a1c3d7e9f\n\
+                    Update the recipe runner to use the new API r=Gijs";
+        assert_eq!(
+            parse(text),
+            vec![summarized(
+                "integration/autoland",
+                "0b5a1c3d7e9f",
+                "Update the recipe runner to use the new API r=Gijs"
+            )]
+        );
+    }
+
+    #[test]
+    fn multi_part_push() {
+        let text = "Pushed by mcooper@mozilla.com:\n\
+                    https://hg.mozilla.org/integration/autoland/rev/1a2b3c4d5e6f\n\
+                    Part 1: Add a schema for recipes r=leplatrem\n\
+                    https://hg.mozilla.org/integration/autoland/rev/6f5e4d3c2b1a\n\
+                    Part 2: Validate recipes against the schema r=leplatrem";
+        assert_eq!(
+            parse(text),
+            vec![
+                summarized(
+                    "integration/autoland",
+                    "1a2b3c4d5e6f",
+                    "Part 1: Add a schema for recipes r=leplatrem"
+                ),
+                summarized(
+                    "integration/autoland",
+                    "6f5e4d3c2b1a",
+                    "Part 2: Validate recipes against the schema r=leplatrem"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn full_hash_with_trailing_text() {
+        let text = "Landed as https://hg.mozilla.org/mozilla-central/rev/e4f3c1a2b5d6978877665544332211aabbccddee. Thanks!";
+        assert_eq!(
+            parse(text),
+            vec![landing(
+                "mozilla-central",
+                "e4f3c1a2b5d6978877665544332211aabbccddee"
+            )]
+        );
+    }
+
+    #[test]
+    fn uplift() {
+        let text = "uplift\nhttps://hg.mozilla.org/releases/mozilla-beta/rev/7d3e2f1a0b9c";
+        assert_eq!(
+            parse(text),
+            vec![landing("releases/mozilla-beta", "7d3e2f1a0b9c")]
+        );
+    }
+
+    #[test]
+    fn markdown_and_angle_brackets() {
+        let text = "Merged: [link](https://hg.mozilla.org/mozilla-central/rev/a1b2c3d4e5f6) \
+                    and <https://hg.mozilla.org/comm-central/rev/0f1e2d3c4b5a>";
+        assert_eq!(
+            parse(text),
+            vec![
+                landing("mozilla-central", "a1b2c3d4e5f6"),
+                landing("comm-central", "0f1e2d3c4b5a"),
+            ]
+        );
+    }
+
+    #[test]
+    fn pushlog() {
+        let text = "Pushlog: https://hg.mozilla.org/integration/autoland/pushloghtml?changeset=3a4b5c6d7e8f\n\
+                    Range: https://hg.mozilla.org/mozilla-central/pushloghtml?fromchange=111111111111&tochange=222222222222";
+        assert_eq!(
+            parse(text),
+            vec![
+                landing("integration/autoland", "3a4b5c6d7e8f"),
+                landing("mozilla-central", "222222222222"),
+            ]
+        );
+    }
+
+    #[test]
+    fn file_links_are_not_landings() {
+        let text = "See https://hg.mozilla.org/mozilla-central/file/tip/browser/app/profile/firefox.js \
+                    and https://searchfox.org/mozilla-central/rev/0123456789ab/dom/base/Element.cpp";
+        assert_eq!(parse(text), vec![]);
+    }
+
+    #[test]
+    fn short_or_invalid_hashes_are_ignored() {
+        let text = "https://hg.mozilla.org/mozilla-central/rev/abc123 \
+                    https://hg.mozilla.org/mozilla-central/rev/tip \
+                    https://hg.mozilla.org/mozilla-central/rev/";
+        assert_eq!(parse(text), vec![]);
+    }
+
+    #[test]
+    fn no_landings() {
+        let text = "Comment on attachment 9201234\nBug 1690000 - Part 1: Refactor the thing r=mythmon\n\nLooks good!";
+        assert_eq!(parse(text), vec![]);
+    }
+
+    #[test]
+    fn successor_for_each_part() {
+        let comments = comments(&[
+            "Pushed by mcooper@mozilla.com:\n\
+             https://hg.mozilla.org/integration/autoland/rev/1a2b3c4d5e6f\n\
+             Part 1: Add a schema for recipes r=leplatrem\n\
+             https://hg.mozilla.org/integration/autoland/rev/6f5e4d3c2b1a\n\
+             Part 2: Validate recipes against the schema r=leplatrem",
+            "https://hg.mozilla.org/mozilla-central/rev/1a2b3c4d5e6f\n\
+             https://hg.mozilla.org/mozilla-central/rev/6f5e4d3c2b1a",
+        ]);
+
+        let part1 = revision("Bug 1690000 - Part 1: Add a schema for recipes r?leplatrem");
+        let part2 =
+            revision("Bug 1690000 - Part 2: Validate recipes against the schema. r=leplatrem");
+        let part3 = revision("Bug 1690000 - Part 3: Remove the old validator");
+
+        let found1 = find_successor(&part1, &comments, &[MOZILLA_CENTRAL], &[]).unwrap();
+        assert_eq!(found1.landing.hash, "1a2b3c4d5e6f");
+        assert_eq!(found1.matched_by, Match::Subject);
+        let found2 = find_successor(&part2, &comments, &[MOZILLA_CENTRAL], &[]).unwrap();
+        assert_eq!(found2.landing.hash, "6f5e4d3c2b1a");
+        assert_eq!(found2.matched_by, Match::Subject);
+        assert_eq!(
+            find_successor(&part3, &comments, &[MOZILLA_CENTRAL], &[]),
+            Err(NoSuccessor::NoLanding)
+        );
+    }
+
+    #[test]
+    fn successor_without_summaries() {
+        let comments = comments(&["https://hg.mozilla.org/mozilla-central/rev/4b2a7b2d3e8e"]);
+        let rev = revision("Bug 1690000 - Fix the thing r=mythmon");
+        assert_eq!(
+            find_successor(&rev, &comments, &[MOZILLA_CENTRAL], &[]),
+            Ok(Successor {
+                landing: landing("mozilla-central", "4b2a7b2d3e8e"),
+                matched_by: Match::OnlyLanding,
+            })
+        );
+    }
+
+    #[test]
+    fn ambiguous_successor_without_summaries() {
+        let comments = comments(
+            &["https://hg.mozilla.org/mozilla-central/rev/8a1d5bd2d4c5\n\
+             https://hg.mozilla.org/mozilla-central/rev/9c3ab7e4e0a1"],
+        );
+        let rev = revision("Bug 1690000 - Fix the thing r=mythmon");
+        assert_eq!(
+            find_successor(&rev, &comments, &[MOZILLA_CENTRAL], &[]),
+            Err(NoSuccessor::NoLanding)
+        );
+    }
+
+    #[test]
+    fn successor_in_other_repositories() {
+        let comments = comments(&["Pushed by mcooper@mozilla.com:\n\
+             https://hg.mozilla.org/integration/autoland/rev/0b5a1c3d7e9f\n\
+             Fix the thing r=mythmon"]);
+        let rev = revision("Bug 1690000 - Fix the thing r=mythmon");
+        let found = find_successor(
+            &rev,
+            &comments,
+            &[MOZILLA_CENTRAL, "integration/autoland"],
+            &[],
+        )
+        .unwrap();
+        assert_eq!(found.landing.repository, "integration/autoland");
+        assert_eq!(found.matched_by, Match::Subject);
+    }
+
+    #[test]
+    fn successor_must_be_in_central() {
+        let comments = comments(&["Pushed by mcooper@mozilla.com:\n\
+             https://hg.mozilla.org/integration/autoland/rev/0b5a1c3d7e9f\n\
+             Fix the thing r=mythmon"]);
+        let rev = revision("Bug 1690000 - Fix the thing r=mythmon");
+        assert_eq!(
+            find_successor(&rev, &comments, &[MOZILLA_CENTRAL], &[]),
+            Err(NoSuccessor::NoLanding)
+        );
+    }
+
+    #[test]
+    fn backout_of_named_changesets() {
+        let text = "Backed out changeset 0b5a1c3d7e9f (bug 1690000) for causing bc failures.\n\n\
+                    Backout link: https://hg.mozilla.org/integration/autoland/rev/5e6f7a8b9c0d\n\n\
+                    Push with failures: https://treeherder.mozilla.org/jobs?repo=autoland&revision=0b5a1c3d7e9f\n\n\
+                    Failure log: https://treeherder.mozilla.org/logviewer?job_id=330112233&repo=autoland&lineNumber=2410";
+        assert_eq!(
+            parse_backout(text),
+            Some(Backout::Changesets(vec!["0b5a1c3d7e9f".to_string()]))
+        );
+        assert_eq!(
+            parse(text),
+            vec![landing("integration/autoland", "5e6f7a8b9c0d")]
+        );
+    }
+
+    #[test]
+    fn backout_of_unnamed_changesets() {
+        let text =
+            "Backed out 2 changesets (bug 1690000) for causing xpcshell failures. CLOSED TREE\n\
+                    Backout link: https://hg.mozilla.org/integration/autoland/rev/5e6f7a8b9c0d";
+        assert_eq!(parse_backout(text), Some(Backout::Earlier));
+    }
+
+    #[test]
+    fn backout_push() {
+        let text = "Backout by nerli@mozilla.com:\n\
+                    https://hg.mozilla.org/integration/autoland/rev/5e6f7a8b9c0d\n\
+                    Backed out changeset 0b5a1c3d7e9f for causing mochitest failures";
+        assert_eq!(
+            parse_backout(text),
+            Some(Backout::Changesets(vec!["0b5a1c3d7e9f".to_string()]))
+        );
+    }
+
+    #[test]
+    fn backout_push_of_several_changesets() {
+        let text = "Backout by nerli@mozilla.com:\n\
+                    https://hg.mozilla.org/integration/autoland/rev/5e6f7a8b9c0d\n\
+                    Backed out changeset 6f5e4d3c2b1a \n\
+                    https://hg.mozilla.org/integration/autoland/rev/d0c9b8a7f6e5\n\
+                    Backed out changeset 1a2b3c4d5e6f for causing xpcshell failures on test_recipes.js. CLOSED TREE";
+        assert_eq!(
+            parse_backout(text),
+            Some(Backout::Changesets(vec![
+                "6f5e4d3c2b1a".to_string(),
+                "1a2b3c4d5e6f".to_string()
+            ]))
+        );
+    }
+
+    #[test]
+    fn not_a_backout() {
+        assert_eq!(
+            parse_backout("We should have backed out 0b5a1c3d7e9f instead."),
+            None
+        );
+        assert_eq!(
+            parse_backout("https://hg.mozilla.org/mozilla-central/rev/0b5a1c3d7e9f"),
+            None
+        );
+    }
+
+    #[test]
+    fn successor_after_reland() {
+        let comments = comments(&[
+            "Pushed by mcooper@mozilla.com:\n\
+             https://hg.mozilla.org/integration/autoland/rev/0b5a1c3d7e9f\n\
+             Fix the thing r=mythmon",
+            "https://hg.mozilla.org/mozilla-central/rev/0b5a1c3d7e9f",
+            "Backed out changeset 0b5a1c3d7e9f (bug 1690000) for causing bc failures.\n\
+             Backout link: https://hg.mozilla.org/integration/autoland/rev/5e6f7a8b9c0d",
+            "https://hg.mozilla.org/mozilla-central/rev/5e6f7a8b9c0d",
+            "Pushed by mcooper@mozilla.com:\n\
+             https://hg.mozilla.org/integration/autoland/rev/a1b2c3d4e5f6\n\
+             Fix the thing r=mythmon",
+            "https://hg.mozilla.org/mozilla-central/rev/a1b2c3d4e5f6",
+        ]);
+        let rev = revision("Bug 1690000 - Fix the thing r=mythmon");
+        let found = find_successor(&rev, &comments, &[MOZILLA_CENTRAL], &[]).unwrap();
+        assert_eq!(found.landing.hash, "a1b2c3d4e5f6");
+        assert_eq!(found.matched_by, Match::Subject);
+    }
+
+    #[test]
+    fn successor_after_reland_without_summaries() {
+        let comments = comments(&[
+            "https://hg.mozilla.org/mozilla-central/rev/0b5a1c3d7e9f",
+            "Backed out 1 changesets (bug 1690000) for causing bc failures.\n\
+             Backout link: https://hg.mozilla.org/integration/autoland/rev/5e6f7a8b9c0d",
+            "https://hg.mozilla.org/mozilla-central/rev/5e6f7a8b9c0d",
+            "https://hg.mozilla.org/mozilla-central/rev/a1b2c3d4e5f6",
+        ]);
+        let rev = revision("Bug 1690000 - Fix the thing r=mythmon");
+        assert_eq!(
+            find_successor(&rev, &comments, &[MOZILLA_CENTRAL], &[]),
+            Ok(Successor {
+                landing: landing("mozilla-central", "a1b2c3d4e5f6"),
+                matched_by: Match::OnlyLanding,
+            })
+        );
+    }
+
+    #[test]
+    fn backed_out_landing_is_not_a_successor() {
+        let comments = comments(&[
+            "Pushed by mcooper@mozilla.com:\n\
+             https://hg.mozilla.org/integration/autoland/rev/0b5a1c3d7e9f\n\
+             Fix the thing r=mythmon",
+            "Backed out changeset 0b5a1c3d7e9f (bug 1690000) for causing bc failures.\n\
+             Backout link: https://hg.mozilla.org/integration/autoland/rev/5e6f7a8b9c0d",
+            "https://hg.mozilla.org/mozilla-central/rev/0b5a1c3d7e9f\n\
+             https://hg.mozilla.org/mozilla-central/rev/5e6f7a8b9c0d",
+        ]);
+        let rev = revision("Bug 1690000 - Fix the thing r=mythmon");
+        assert_eq!(
+            find_successor(&rev, &comments, &[MOZILLA_CENTRAL], &[]),
+            Err(NoSuccessor::BackedOut)
+        );
+    }
+
+    #[test]
+    fn backed_out_landing_without_summaries() {
+        let comments = comments(&[
+            "https://hg.mozilla.org/mozilla-central/rev/0b5a1c3d7e9f",
+            "Backout by nerli@mozilla.com:\n\
+             https://hg.mozilla.org/integration/autoland/rev/5e6f7a8b9c0d\n\
+             Backed out changeset 0b5a1c3d7e9f for causing bc failures",
+            "https://hg.mozilla.org/mozilla-central/rev/5e6f7a8b9c0d",
+        ]);
+        let rev = revision("Bug 1690000 - Fix the thing r=mythmon");
+        assert_eq!(
+            find_successor(&rev, &comments, &[MOZILLA_CENTRAL], &[]),
+            Err(NoSuccessor::BackedOut)
+        );
+    }
+
+    #[test]
+    fn successor_by_differential() {
+        let comments = comments(&[
+            "Pushed by mcooper@mozilla.com:\n\
+             https://hg.mozilla.org/integration/autoland/rev/1a2b3c4d5e6f\n\
+             Part 1: Add a schema for recipes r=leplatrem\n\
+             https://hg.mozilla.org/integration/autoland/rev/6f5e4d3c2b1a\n\
+             Part 2: Validate recipes r=leplatrem",
+            "https://hg.mozilla.org/mozilla-central/rev/1a2b3c4d5e6f\n\
+             https://hg.mozilla.org/mozilla-central/rev/6f5e4d3c2b1a",
+        ]);
+        let landed = [
+            Revision::new(
+                "1a2b3c4d5e6f7777777777777777777777777777",
+                "Bug 1690000 - Part 1: Add a schema for recipes r=leplatrem\n\n\
+                 Differential Revision: https://phabricator.services.mozilla.com/D104000",
+            ),
+            Revision::new(
+                "6f5e4d3c2b1a8888888888888888888888888888",
+                "Bug 1690000 - Part 2: Validate recipes r=leplatrem\n\n\
+                 Differential Revision: https://phabricator.services.mozilla.com/D104001",
+            ),
+        ];
+
+        // The subject was reworded before landing, so only the Differential Revision matches.
+        let rev = revision(
+            "Bug 1690000 - Part 2: Check recipes against the schema r?leplatrem\n\n\
+             Differential Revision: https://phabricator.services.mozilla.com/D104001",
+        );
+        let found = find_successor(&rev, &comments, &[MOZILLA_CENTRAL], &landed).unwrap();
+        assert_eq!(found.landing.hash, "6f5e4d3c2b1a");
+        assert_eq!(found.matched_by, Match::Differential);
+
+        let other = revision(
+            "Bug 1690000 - Part 3: Remove the old validator\n\n\
+             Differential Revision: https://phabricator.services.mozilla.com/D104002",
+        );
+        assert_eq!(
+            find_successor(&other, &comments, &[MOZILLA_CENTRAL], &landed),
+            Err(NoSuccessor::NoLanding)
+        );
+    }
+
+    #[test]
+    fn local_successor() {
+        let public = [
+            Revision::new(
+                "1111111111111111111111111111111111111111",
+                "Bug 1690000 - Part 1: Add a schema for recipes r=leplatrem\n\n\
+                 Differential Revision: https://phabricator.services.mozilla.com/D104000",
+            ),
+            Revision::new(
+                "2222222222222222222222222222222222222222",
+                "Bug 1690001 - Part 1: Add a schema for recipes r=leplatrem",
+            ),
+            Revision::new(
+                "3333333333333333333333333333333333333333",
+                "Bug 1690000 - Part 2: Validate recipes r=leplatrem\n\n\
+                 Differential Revision: https://phabricator.services.mozilla.com/D104001",
+            ),
+        ];
+
+        let part1 = revision("Bug 1690000 - Part 1: Add a schema for recipes r?leplatrem");
+        let found = find_local_successor(&part1, &public).unwrap();
+        assert_eq!(found.landing.hash, public[0].hash);
+        assert_eq!(found.matched_by, Match::Subject);
+
+        let part2 = revision(
+            "Bug 1690000 - Part 2: Check recipes r?leplatrem\n\n\
+             Differential Revision: https://phabricator.services.mozilla.com/D104001",
+        );
+        let found = find_local_successor(&part2, &public).unwrap();
+        assert_eq!(found.landing.hash, public[2].hash);
+        assert_eq!(found.matched_by, Match::Differential);
+
+        let part3 = revision("Bug 1690000 - Part 3: Remove the old validator");
+        assert_eq!(
+            find_local_successor(&part3, &public),
+            Err(NoSuccessor::NoLanding)
+        );
+    }
+
+    #[test]
+    fn local_successor_backed_out() {
+        let mut public = vec![
+            Revision::new(
+                "1111111111111111111111111111111111111111",
+                "Bug 1690000 - Fix the thing r=mythmon\n\n\
+                 Differential Revision: https://phabricator.services.mozilla.com/D104000",
+            ),
+            Revision::new(
+                "2222222222222222222222222222222222222222",
+                "Backed out changeset 111111111111 (bug 1690000) for causing bc failures. CLOSED TREE",
+            ),
+        ];
+        let rev = revision(
+            "Bug 1690000 - Fix the thing r=mythmon\n\n\
+             Differential Revision: https://phabricator.services.mozilla.com/D104000",
+        );
+        assert_eq!(
+            find_local_successor(&rev, &public),
+            Err(NoSuccessor::BackedOut)
+        );
+
+        public.push(Revision::new(
+            "3333333333333333333333333333333333333333",
+            "Bug 1690000 - Fix the thing r=mythmon\n\n\
+             Differential Revision: https://phabricator.services.mozilla.com/D104000",
+        ));
+        let found = find_local_successor(&rev, &public).unwrap();
+        assert_eq!(found.landing.hash, public[2].hash);
+    }
+}
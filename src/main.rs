@@ -20,128 +20,720 @@
 )]
 
 pub mod bz;
+pub mod cache;
+pub mod cmdserver;
+pub mod config;
 pub mod hg;
+pub mod http;
+pub mod journal;
+pub mod landing;
+pub mod phab;
+pub mod report;
+pub mod repository;
+pub mod source;
+pub mod stack;
 
-use crate::{bz::BugStatus, hg::Hg};
+use crate::{
+    cache::{CachingClient, Mode},
+    config::{BugzillaConfig, Config, Interaction},
+    hg::{Hg, Revision},
+    journal::{Entry, Journal},
+    report::{Decision, Format, Record, SkipReason},
+    source::{Kind, LandingSource},
+    stack::Stacks,
+};
 use anyhow::{Context, Result};
 use async_std::io::{self, prelude::WriteExt};
-use bz::ApiBug;
 use clap::Clap;
-use futures::stream::{self, StreamExt, TryStreamExt};
-use hg::Revision;
+use futures::{
+    channel::mpsc,
+    stream::{self, StreamExt, TryStreamExt},
+};
+use landing::Policy;
 use std::{
-    path::PathBuf,
+    cell::RefCell,
+    collections::HashMap,
+    env,
+    path::{Path, PathBuf},
+    process,
     sync::atomic::{AtomicU32, Ordering},
+    time::SystemTime,
 };
 
+/// The environment variable that can hold a Bugzilla API key.
+const API_KEY_VAR: &str = "BUGZILLA_API_KEY";
+
+/// The environment variable that can hold a Phabricator API token.
+const API_TOKEN_VAR: &str = "PHABRICATOR_API_TOKEN";
+
+/// The exit status used when running without prompts and nothing was pruned.
+const NOTHING_PRUNED_STATUS: i32 = 3;
+
+/// The revisions considered when no revset is configured.
+const DEFAULT_REVSET: &str = "draft() and not(obsolete())";
+
+/// How many revisions are examined at once when no number is configured.
+const DEFAULT_JOBS: usize = 8;
+
 #[derive(Clap)]
+#[allow(clippy::struct_excessive_bools)] // Command line flags are naturally booleans.
 struct Opts {
     #[clap(short, long, default_value = ".")]
     path: PathBuf,
+
+    /// The config file to read, instead of the one in the user's config
+    /// directory. Settings in the repository's .hg/hg-bz-prune.toml still
+    /// take precedence.
+    #[clap(long)]
+    config: Option<PathBuf>,
+
+    /// Which revisions to consider, as a revset or the name of a preset from
+    /// the config file. Defaults to drafts that aren't obsolete.
+    #[clap(short, long)]
+    rev: Option<String>,
+
+    /// The base URL of the Bugzilla instance to query.
+    #[clap(long)]
+    bugzilla_url: Option<String>,
+
+    /// How many revisions to search for landings at once. Defaults to 8.
+    #[clap(short, long)]
+    jobs: Option<usize>,
+
+    /// Pull before looking for drafts. This is the default, except with
+    /// --dry-run.
+    #[clap(long, conflicts_with = "no-pull")]
+    pull: bool,
+
+    /// Don't pull before looking for drafts.
+    #[clap(long)]
+    no_pull: bool,
+
+    /// Ignore cached Bugzilla data and fetch everything again.
+    #[clap(long, conflicts_with = "offline")]
+    refresh: bool,
+
+    /// Don't pull or contact Bugzilla, and only use cached Bugzilla data.
+    #[clap(long)]
+    offline: bool,
+
+    /// Prompt before pruning each revision. This is the default, unless the
+    /// config file sets another mode.
+    #[clap(long, conflicts_with_all = &["dry-run", "yes"])]
+    prompt: bool,
+
+    /// Report what would be pruned, without prompting or pruning anything.
+    #[clap(short = 'n', long)]
+    dry_run: bool,
+
+    /// Prune revisions that satisfy the policy without prompting. Exits with
+    /// status 3 if nothing was pruned.
+    #[clap(short, long, conflicts_with = "dry-run")]
+    yes: bool,
+
+    /// After pruning, move descendants that were left orphaned onto the
+    /// successors of their pruned ancestors with hg evolve. This also lets
+    /// --yes prune revisions that have descendants.
+    #[clap(long, conflicts_with = "no-restack")]
+    restack: bool,
+
+    /// Don't restack orphaned descendants after pruning. This is the default.
+    #[clap(long)]
+    no_restack: bool,
+
+    /// Which revisions to prune without prompting: "any" prunable revision, or
+    /// only those whose "subject" matches the landed changeset.
+    #[clap(long, default_value = "any")]
+    policy: Policy,
+
+    /// How to write out results: "text", or "json" with one object per draft
+    /// revision. JSON output requires --dry-run or --yes.
+    #[clap(long, default_value = "text")]
+    format: Format,
+
+    /// Where to look for landings: "bugzilla" comments, "local" public
+    /// changesets, or "phabricator". Several sources can be combined, and the
+    /// most confident successor any of them finds is used.
+    #[clap(long = "source", default_value = "bugzilla", use_delimiter = true)]
+    sources: Vec<Kind>,
+
+    /// Find successors among the public changesets in the local repository,
+    /// instead of in Bugzilla comments. Bugzilla is only used to confirm that
+    /// bugs are fixed, when it can be reached. The same as --source local.
+    #[clap(long, conflicts_with = "sources")]
+    local: bool,
+
+    /// Finish with a table explaining what was decided about every draft
+    /// revision, including why revisions were skipped.
+    #[clap(short, long, alias = "explain")]
+    verbose: bool,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Clap)]
+enum Command {
+    /// Revive the revisions pruned by the last session, or by another one.
+    /// Descendants that were restacked onto successors are left where they
+    /// are.
+    Undo(Undo),
+}
+
+#[derive(Clap)]
+struct Undo {
+    /// The session to undo, instead of the last one.
+    #[clap(long, conflicts_with = "list")]
+    session: Option<u64>,
+
+    /// List the sessions that can be undone.
+    #[clap(long)]
+    list: bool,
+}
+
+impl Opts {
+    /// The settings given on the command line, which take precedence over
+    /// those in config files.
+    fn to_config(&self) -> Config {
+        let mode = if self.prompt {
+            Some(Interaction::Prompt)
+        } else if self.dry_run {
+            Some(Interaction::DryRun)
+        } else if self.yes {
+            Some(Interaction::Yes)
+        } else {
+            None
+        };
+        Config {
+            pull: flag(self.pull, self.no_pull),
+            rev: self.rev.clone(),
+            jobs: self.jobs,
+            mode,
+            restack: flag(self.restack, self.no_restack),
+            bugzilla: BugzillaConfig {
+                url: self.bugzilla_url.clone(),
+                ..BugzillaConfig::default()
+            },
+            ..Config::default()
+        }
+    }
+}
+
+/// Combine a pair of flags that turn a setting on and off, if either was given.
+const fn flag(on: bool, off: bool) -> Option<bool> {
+    if on {
+        Some(true)
+    } else if off {
+        Some(false)
+    } else {
+        None
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let opts = Opts::parse();
+    let opts = &Opts::parse();
+    if let Some(Command::Undo(undo_opts)) = &opts.command {
+        return undo(&opts.path, undo_opts).await;
+    }
+    // Flags on the command line take precedence over the config files
+    let config = Config::load(opts.config.as_deref(), &opts.path)?.merge(opts.to_config());
+    let mode = config.mode.unwrap_or(Interaction::Prompt);
+    // A dry run shouldn't change the repository unless asked to
+    let pull = config.pull.unwrap_or(mode != Interaction::DryRun);
+    let restack = config.restack.unwrap_or(false);
+    let jobs = config.jobs.unwrap_or(DEFAULT_JOBS);
+    let revset = config.revset(config.rev.as_deref().unwrap_or(DEFAULT_REVSET));
+
+    if opts.format == Format::Json && mode == Interaction::Prompt {
+        anyhow::bail!("JSON output can't be combined with prompts, use --dry-run or --yes");
+    }
+
+    let hg = &Hg::with_command_server(&opts.path);
+    let repositories = &config.repositories(hg);
+
+    // Catch mistakes in the revset before doing anything slow
+    hg.validate_revset(revset).await?;
 
-    let hg = &Hg::new(&opts.path);
+    let cache_mode = if opts.offline {
+        Mode::Offline
+    } else if opts.refresh {
+        Mode::Refresh
+    } else {
+        Mode::Normal
+    };
 
     // Try to get up to date revisions, but don't fail if it doesn't work.
-    if let Err(err) = hg.pull().await {
-        println!("Warning, pull failed: {}", err);
+    if pull && cache_mode != Mode::Offline {
+        if let Err(err) = hg.pull().await {
+            eprintln!("Warning, pull failed: {err}");
+        }
     }
 
     // Get draft revisions
-    let revs = hg
-        .log(Some("draft() and not(obsolete())"))
+    let mut revs = hg
+        .log(Some(revset))
         .await
         .context("Failed to get list of draft revisions")?;
+    // Examine ancestors first, so that stacks can be found as records arrive
+    stack::sort(&mut revs);
 
     if revs.is_empty() {
-        println!("No draft revisions found");
+        if opts.format == Format::Text {
+            println!("No draft revisions found");
+        }
         return Ok(());
     }
 
-    // Prepare an HTTP client to attach to bugs
-    let client = reqwest::Client::new();
-    // Set up a counter for how many prunable revisions are found
+    // Find out how the drafts are stacked, to avoid orphaning descendants
+    let mutable = hg
+        .mutable()
+        .await
+        .context("Failed to get list of mutable revisions")?;
+    let stacks = &Stacks::new(&mutable, &revs);
+
+    // Prepare a Bugzilla client to attach to bugs
+    let mut bz_config = config.bugzilla.to_client_config();
+    // Prefer an API key from the environment, then the config file, then the
+    // one that Mercurial extensions such as moz-phab store in hgrc.
+    if let Ok(api_key) = env::var(API_KEY_VAR) {
+        bz_config.api_key = Some(api_key);
+    } else if bz_config.api_key.is_none() {
+        bz_config.api_key = hg
+            .config("bugzilla.apikey")
+            .await
+            .context("Failed to read Bugzilla API key from hgrc")?;
+    }
+    let client = bz::Client::new(&bz_config).context("Failed to set up Bugzilla client")?;
+    let cache_dir =
+        CachingClient::default_dir(&bz_config.url).context("Could not find a cache directory")?;
+    let bugs = &CachingClient::new(&client, cache_dir, cache_mode);
+    // Set up counters for how many prunable revisions are found, and how many are pruned
     let num_prunable = AtomicU32::new(0);
 
-    // For every revision, look for a bug number in the revision and then scan
-    // that bug for any comments that indicate the draft has merged.
+    let kinds = if opts.local {
+        vec![Kind::Local]
+    } else {
+        opts.sources.clone()
+    };
+
+    // Look up every bug the revisions mention at once. Only Bugzilla needs
+    // these details, so other sources carry on without them.
+    let details = if kinds
+        .iter()
+        .any(|kind| matches!(kind, Kind::Bugzilla | Kind::Local))
+    {
+        bugs.details_many(revs.iter().flat_map(Revision::bugs).map(|bug| bug.id))
+            .await
+    } else {
+        Ok(HashMap::new())
+    };
+    let details = &match details {
+        Ok(details) => details,
+        Err(err) if !kinds.contains(&Kind::Bugzilla) => {
+            eprintln!("Warning, could not check bugs are fixed: {err}");
+            HashMap::new()
+        }
+        Err(err) => return Err(err).context("Failed to fetch bug details"),
+    };
+
+    let phab_client = if kinds.contains(&Kind::Phabricator) {
+        let mut phab_config = config.phabricator.to_client_config();
+        if let Ok(api_token) = env::var(API_TOKEN_VAR) {
+            phab_config.api_token = Some(api_token);
+        } else if phab_config.api_token.is_none() {
+            phab_config.api_token = config::arcrc_token(&phab_config.url);
+        }
+        Some(phab::Client::new(&phab_config).context("Failed to set up Phabricator client")?)
+    } else {
+        None
+    };
+
+    let mut sources: Vec<Box<dyn LandingSource>> = Vec::new();
+    for kind in kinds {
+        sources.push(match (kind, &phab_client) {
+            (Kind::Bugzilla, _) => {
+                Box::new(source::Bugzilla::new(&client, bugs, details, repositories))
+            }
+            (Kind::Local, _) => Box::new(source::Local::new(details, hg)),
+            (Kind::Phabricator, Some(phab_client)) => {
+                Box::new(source::Phabricator::new(phab_client, hg))
+            }
+            (Kind::Phabricator, None) => unreachable!("Phabricator client is set up when enabled"),
+        });
+    }
+    let sources = &sources;
+
+    // For every revision, ask each landing source whether the draft has
+    // merged, for example by scanning the comments of the bug it mentions.
     //
     // The intent is that once a revision that appears prunable is found, the
     // user will be prompted immediately. At the same time, the search will
     // continue. The time the user spends considering the choice will be used to
-    // continue searching for more prunable revisions.
-    stream::iter(revs)
-        // Add an API to every bug
-        .filter_map(|rev: Revision| async { rev.bug().map(|bug| Ok((rev, bug.with_api(&client)))) })
-        // Remove bugs thats aren't resolved or verified
-        .try_filter_map(|(rev, bug)| async {
-            let details = bug.details().await?;
-            let v: Result<_, anyhow::Error> =
-                if details.status == BugStatus::Resolved || details.status == BugStatus::Verified {
-                    Ok(Some((rev, bug)))
-                } else {
-                    Ok(None)
-                };
-            v
-        })
-        // Find bugs that mention a merge to mozilla-central, starting with the oldest
-        .try_filter_map(|(rev, bug): (Revision, ApiBug)| async move {
-            let mut comments = bug.comments().await?;
-            comments.reverse();
-            for comment in comments {
-                if comment
-                    .raw_text
-                    .starts_with("https://hg.mozilla.org/mozilla-central/rev/")
-                {
-                    let hash = comment.raw_text.split('/').last().unwrap();
-                    if hash.chars().all(|c| c.is_ascii_hexdigit()) {
-                        return Ok(Some((rev, hash.to_string())));
+    // continue searching for more prunable revisions. To allow that, the search
+    // runs separately from the prompts and sends what it finds over a channel.
+    //
+    // Several revisions are searched at once, but results are still produced
+    // in the same order as the drafts.
+    let (found_tx, found_rx) = mpsc::unbounded();
+    let search = stream::iter(&revs)
+        .map(|rev| source::examine(sources, rev))
+        .buffered(jobs.max(1))
+        .map(|record| Ok(Ok(record)))
+        .forward(found_tx);
+
+    // Revisions that will be pruned along with an ancestor, once it was
+    // decided to prune their whole stack.
+    let stacked = &RefCell::new(HashMap::new());
+    let accepted_by_policy =
+        |record: &Record| record.matched_by.is_some_and(|m| opts.policy.accepts(m));
+    // Without prompts, descendants are only pruned if they pass the policy.
+    let will_prune = move |record: &Record| {
+        record.decision == Decision::Prunable
+            && (mode != Interaction::Yes || accepted_by_policy(record))
+    };
+
+    let prompts = stack::annotate(found_rx, stacks, will_prune)
+        // For each prunable revision, prompt the user if it should be pruned.
+        .and_then(|mut record: Record| async {
+            match record.decision {
+                Decision::Prunable => num_prunable.fetch_add(1, Ordering::SeqCst),
+                _ => return Ok(record),
+            };
+            if let Some(decision) = stacked.borrow_mut().remove(&record.hash) {
+                record.decision = decision;
+                return Ok(record);
+            }
+
+            record.decision = match mode {
+                Interaction::DryRun => Decision::WouldPrune,
+                Interaction::Yes => {
+                    if !accepted_by_policy(&record) {
+                        Decision::Skipped(SkipReason::Policy)
+                    } else if !record.orphans.is_empty() && !restack {
+                        Decision::Skipped(SkipReason::WouldOrphan)
+                    } else {
+                        Decision::Accepted
                     }
                 }
-            }
-            Ok(None)
-        })
-        // For each prunable revision, prompt the user if it should be pruned.
-        .try_filter_map(|(revision, successor)| async {
-            num_prunable.fetch_add(1, Ordering::SeqCst);
-
-            let stdin = io::stdin();
-            let mut stdout = io::stdout();
-            let mut buffer = String::new();
-
-            print!(
-                "{} {}\n  prune to {}? ",
-                &revision.hash[..12],
-                revision.subject().unwrap_or("<no description>"),
-                successor
-            );
-            loop {
-                print!("[Yn] > ");
-                stdout.flush().await?;
-                stdin.read_line(&mut buffer).await?;
-                match buffer.trim().to_lowercase().as_str() {
-                    "y" | "" => {
-                        return Ok(Some((revision.hash, successor)));
+                Interaction::Prompt if !record.stack.is_empty() => {
+                    let question = format!(
+                        "{record}\n  landed with descendants {}: prune stack? ",
+                        short_hashes(&record.stack)
+                    );
+                    if prompt(&question).await? {
+                        stacked.borrow_mut().extend(
+                            record
+                                .stack
+                                .iter()
+                                .map(|hash| (hash.clone(), Decision::Accepted)),
+                        );
+                        Decision::Accepted
+                    } else {
+                        Decision::Declined
                     }
-                    "n" => return Ok(None),
-                    _ => (),
                 }
+                Interaction::Prompt => {
+                    if !record.orphans.is_empty() {
+                        let fate = if restack { "restack" } else { "orphan" };
+                        println!(
+                            "Warning, pruning {} will {fate} {}",
+                            record.short_hash(),
+                            short_hashes(&record.orphans)
+                        );
+                    }
+                    if prompt(&format!("{record}: prune? ")).await? {
+                        Decision::Accepted
+                    } else {
+                        Decision::Declined
+                    }
+                }
+            };
+            Ok(record)
+        })
+        // Report what was decided, apart from revisions that will be pruned,
+        // which are reported once they have been. JSON output keeps the
+        // order of the drafts, so all of it waits for pruning.
+        .and_then(|record| async move {
+            if opts.format == Format::Text && record.decision != Decision::Accepted {
+                report_text(&record, mode == Interaction::Yes);
             }
+            Ok(record)
         })
-        // And finally prune the revisions
-        .try_for_each(|(hash, successor)| async move {
-            hg.prune(&hash, Some(&successor)).await?;
-            Ok(())
+        .try_collect::<Vec<_>>();
+    let search = async {
+        Box::pin(search)
+            .await
+            .context("Search for prunable revisions stopped unexpectedly")
+    };
+    let ((), mut records) = futures::try_join!(search, Box::pin(prompts))?;
+
+    // Prune the accepted revisions in as few commands as possible. A failure
+    // only affects the revisions it happened to, so record it and carry on
+    // with the rest.
+    let accepted: Vec<&mut Record> = records
+        .iter_mut()
+        .filter(|record| record.decision == Decision::Accepted)
+        .collect();
+    let prunes: Vec<(&str, Option<&str>)> = accepted
+        .iter()
+        .map(|record| {
+            // Successors can only be recorded if they're in this repository
+            let successor = record.successor.as_deref().filter(|_| {
+                record
+                    .repository
+                    .as_deref()
+                    .is_none_or(|name| repositories.is_here(name))
+            });
+            (record.hash.as_str(), successor)
         })
-        .await?;
+        .collect();
+    let mut results: Vec<Option<Result<(), hg::Error>>> = prunes.iter().map(|_| None).collect();
+    let mut session = None;
+    let mut batches = Box::pin(hg.prune_many(&prunes));
+    while let Some(batch) = batches.next().await {
+        let mut pruned = Vec::new();
+        for (index, result) in batch {
+            if result.is_ok() {
+                let (hash, successor) = prunes[index];
+                pruned.push(Entry {
+                    session: 0,
+                    hash: hash.to_string(),
+                    successor: successor.map(ToString::to_string),
+                    time: SystemTime::now(),
+                });
+            }
+            results[index] = Some(result);
+        }
+        // Keep track of what was pruned straight away, so that it can be
+        // undone even if the run doesn't finish
+        if let Err(err) = record_pruned(&opts.path, &mut session, pruned).await {
+            eprintln!("Warning, pruned revisions can't be undone: {err:#}");
+        }
+    }
+    drop(batches);
+    for (index, record) in accepted.into_iter().enumerate() {
+        match results[index].take() {
+            Some(Ok(())) => record.decision = Decision::Pruned,
+            Some(Err(err)) => {
+                record.error = Some(err.to_string());
+                record.decision = Decision::PruneFailed;
+            }
+            // Every revision is in a batch, so this can't happen
+            None => continue,
+        }
+        if opts.format == Format::Text {
+            report_text(record, mode == Interaction::Yes);
+        }
+    }
+    if opts.format == Format::Json {
+        for record in &records {
+            println!("{}", serde_json::to_string(record)?);
+        }
+    }
+    let num_pruned = records
+        .iter()
+        .filter(|record| record.decision == Decision::Pruned)
+        .count();
+
+    // Move orphaned descendants onto the successors of what was pruned
+    let orphaning: Vec<&str> = records
+        .iter()
+        .filter(|record| record.decision == Decision::Pruned && !record.orphans.is_empty())
+        .map(|record| record.hash.as_str())
+        .collect();
+    if restack && !orphaning.is_empty() {
+        match hg.restack(&orphaning).await {
+            Ok(()) if opts.format == Format::Text => println!("Restacked orphaned descendants"),
+            Ok(()) => (),
+            Err(err) => eprintln!("Warning, restacking failed, finish with hg evolve: {err}"),
+        }
+    }
 
-    if num_prunable.into_inner() == 0 {
+    if num_prunable.into_inner() == 0 && opts.format == Format::Text {
         println!("No prunable revisions found");
     }
 
+    if opts.verbose && opts.format == Format::Text {
+        print!("\n{}", report::table(&records));
+    }
+
+    if mode == Interaction::Yes && num_pruned == 0 {
+        process::exit(NOTHING_PRUNED_STATUS);
+    }
+
+    Ok(())
+}
+
+/// Add revisions that were just pruned to the journal, as part of `session`,
+/// which is numbered when anything is first added.
+async fn record_pruned(
+    path: &Path,
+    session: &mut Option<u64>,
+    mut entries: Vec<Entry>,
+) -> Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let hg_dir = hg::hg_dir(path).context("Repository not found")?;
+    let journal = Journal::new(hg_dir);
+    let number = match *session {
+        Some(number) => number,
+        None => *session.insert(journal.new_session().await?),
+    };
+    for entry in &mut entries {
+        entry.session = number;
+    }
+    journal.record(&entries).await?;
+    Ok(())
+}
+
+/// Revive the revisions pruned by a session, and forget that session. Only
+/// the pruned revisions are revived, so descendants that were restacked stay
+/// on the successors.
+async fn undo(path: &Path, opts: &Undo) -> Result<()> {
+    let hg_dir = hg::hg_dir(path).context("Repository not found")?;
+    let journal = Journal::new(hg_dir);
+    let entries = journal
+        .entries()
+        .await
+        .context("Failed to read the journal")?;
+
+    if opts.list {
+        let mut sessions: Vec<u64> = entries.iter().map(|entry| entry.session).collect();
+        sessions.dedup();
+        if sessions.is_empty() {
+            println!("Nothing to undo");
+        }
+        for session in sessions {
+            let pruned: Vec<&Entry> = entries
+                .iter()
+                .filter(|entry| entry.session == session)
+                .collect();
+            let hashes: Vec<String> = pruned.iter().map(|entry| entry.hash.clone()).collect();
+            println!(
+                "Session {session}, {}: {}",
+                time_ago(pruned[0].time),
+                short_hashes(&hashes)
+            );
+        }
+        return Ok(());
+    }
+
+    let Some(session) = opts
+        .session
+        .or_else(|| entries.iter().map(|entry| entry.session).max())
+    else {
+        println!("Nothing to undo");
+        return Ok(());
+    };
+    let pruned: Vec<&Entry> = entries
+        .iter()
+        .filter(|entry| entry.session == session)
+        .collect();
+    if pruned.is_empty() {
+        anyhow::bail!("Session {} is not in the journal, see undo --list", session);
+    }
+
+    // Revisions pruned with a successor are revived as duplicates, so they
+    // don't diverge from it. hg touch only moves a revision onto its revived
+    // parent within a single command, so the rest of the session is revived
+    // as duplicates along with them rather than orphaned.
+    let hg = Hg::with_command_server(path);
+    let duplicate = pruned.iter().any(|entry| entry.successor.is_some());
+    let revs: Vec<&str> = pruned.iter().map(|entry| entry.hash.as_str()).collect();
+    hg.revive(&revs, duplicate)
+        .await
+        .context("Failed to revive pruned revisions")?;
+    journal
+        .remove_session(session)
+        .await
+        .context("Failed to update the journal")?;
+
+    let hashes: Vec<String> = pruned.iter().map(|entry| entry.hash.clone()).collect();
+    println!("Revived {}", short_hashes(&hashes));
     Ok(())
 }
+
+/// Describe how long ago `time` was, roughly.
+fn time_ago(time: SystemTime) -> String {
+    let seconds = time.elapsed().map_or(0, |elapsed| elapsed.as_secs());
+    let (count, unit) = match seconds {
+        0..=59 => return "just now".to_string(),
+        60..=3599 => (seconds / 60, "minute"),
+        3600..=86399 => (seconds / 3600, "hour"),
+        _ => (seconds / 86400, "day"),
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    format!("{count} {unit}{plural} ago")
+}
+
+/// Ask the user a yes or no question, such as whether a revision should be pruned.
+async fn prompt(question: &str) -> Result<bool> {
+    let stdin = io::stdin();
+    let mut stdout = io::stdout();
+    let mut buffer = String::new();
+
+    print!("{question}");
+    loop {
+        print!("[Yn] > ");
+        stdout.flush().await?;
+        buffer.clear();
+        stdin.read_line(&mut buffer).await?;
+        match buffer.trim().to_lowercase().as_str() {
+            "y" | "" => return Ok(true),
+            "n" => return Ok(false),
+            _ => (),
+        }
+    }
+}
+
+/// List revisions by their abbreviated hashes.
+fn short_hashes(hashes: &[String]) -> String {
+    let hashes: Vec<&str> = hashes
+        .iter()
+        .map(|hash| &hash[..12.min(hash.len())])
+        .collect();
+    hashes.join(", ")
+}
+
+/// Describe what happened to a revision. Revisions that were skipped before
+/// they were found to have landed aren't mentioned.
+fn report_text(record: &Record, announce_prunes: bool) {
+    match (record.decision, &record.error) {
+        (Decision::Skipped(SkipReason::BugUnavailable), Some(err)) => {
+            eprintln!("Warning, skipping {}: {}", record.short_hash(), err);
+        }
+        (Decision::Skipped(SkipReason::SourceFailed), Some(err)) => {
+            let source = record
+                .source
+                .map_or_else(String::new, |kind| format!(" {kind}"));
+            eprintln!(
+                "Warning, skipping {}, the{source} landing source failed: {err}",
+                record.short_hash()
+            );
+        }
+        (Decision::Skipped(SkipReason::SuccessorMissing), _) => {
+            println!("{record}: successor missing, pull needed");
+        }
+        (Decision::Skipped(SkipReason::Policy), _) => println!("{record}: skipped by policy"),
+        (Decision::Skipped(SkipReason::WouldOrphan), _) => {
+            println!(
+                "{record}: skipped, would orphan {}",
+                short_hashes(&record.orphans)
+            );
+        }
+        (Decision::WouldPrune, _) if !record.orphans.is_empty() => {
+            println!(
+                "{record}: would prune, orphaning {}",
+                short_hashes(&record.orphans)
+            );
+        }
+        (Decision::WouldPrune, _) => println!("{record}: would prune"),
+        (Decision::Pruned, _) if announce_prunes => println!("{record}: pruned"),
+        (Decision::PruneFailed, Some(err)) => {
+            eprintln!("Warning, failed to prune {}: {}", record.short_hash(), err);
+        }
+        _ => (),
+    }
+}
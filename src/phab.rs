@@ -0,0 +1,308 @@
+//! An abstraction to interact with the Phabricator Conduit API.
+
+use crate::{http, landing};
+use reqwest::StatusCode;
+use serde::{de::DeserializeOwned, Deserialize};
+use thiserror::Error;
+
+/// The Phabricator instance used when none is configured.
+pub const DEFAULT_URL: &str = "https://phabricator.services.mozilla.com";
+
+/// The type of edge that links a Differential revision to the commits it landed as.
+const COMMIT_EDGE: &str = "revision.commit";
+
+/// A problem that prevents usage of the Phabricator API.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// The API did not return the expected information
+    #[error("The API did not return the expected information")]
+    ApiContract,
+
+    /// No API token is configured, and Conduit can't be used without one
+    #[error("A Phabricator API token is required")]
+    MissingApiToken,
+
+    /// The API reported an error
+    #[error("Phabricator error {code}: {message}")]
+    Api {
+        /// The Conduit error code.
+        code: String,
+        /// A description of the error from Phabricator.
+        message: String,
+    },
+
+    /// The API responded with an unexpected HTTP status
+    #[error("Phabricator responded with status {0}")]
+    Status(StatusCode),
+
+    /// Could not complete API request
+    #[error("Could not complete API request")]
+    Http(#[from] reqwest::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Settings describing how to reach a Phabricator instance.
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// The base URL of the Phabricator instance, without the trailing `/api`.
+    pub url: String,
+
+    /// How to make requests to the instance.
+    pub http: http::Settings,
+
+    /// The Conduit API token used to authenticate every request.
+    pub api_token: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            url: DEFAULT_URL.to_string(),
+            http: http::Settings::default(),
+            api_token: None,
+        }
+    }
+}
+
+/// An HTTP client bound to a specific Phabricator instance.
+#[derive(Debug)]
+pub struct Client {
+    http: reqwest::Client,
+    api_url: String,
+    api_token: String,
+}
+
+impl Client {
+    /// Create a client that talks to the Phabricator instance described by `config`.
+    ///
+    /// # Errors
+    /// Returns an error if no API token is configured, or if the underlying
+    /// HTTP client cannot be constructed.
+    pub fn new(config: &Config) -> Result<Self> {
+        let api_token = config.api_token.clone().ok_or(Error::MissingApiToken)?;
+        let http = config.http.client_builder().build()?;
+        Ok(Self {
+            http,
+            api_url: format!("{}/api", config.url.trim_end_matches('/')),
+            api_token,
+        })
+    }
+
+    /// Get the hashes of the commits that the Differential revision `D<id>`
+    /// landed as. Revisions that haven't landed, or don't exist, have none.
+    ///
+    /// # Errors
+    /// Returns an error if an API request fails or cannot be parsed.
+    pub async fn landed_commits(&self, id: u64) -> Result<Vec<String>> {
+        let revisions: SearchResults<Revision> = self
+            .call(
+                "differential.revision.search",
+                &[("constraints[ids][0]".to_string(), id.to_string())],
+            )
+            .await?;
+        let Some(revision) = revisions.data.into_iter().next() else {
+            return Ok(Vec::new());
+        };
+
+        let edges: SearchResults<Edge> = self
+            .call(
+                "edge.search",
+                &[
+                    ("sourcePHIDs[0]".to_string(), revision.phid),
+                    ("types[0]".to_string(), COMMIT_EDGE.to_string()),
+                ],
+            )
+            .await?;
+        if edges.data.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let params: Vec<(String, String)> = edges
+            .data
+            .into_iter()
+            .enumerate()
+            .map(|(index, edge)| (format!("constraints[phids][{index}]"), edge.destination))
+            .collect();
+        let commits: SearchResults<Commit> = self.call("diffusion.commit.search", &params).await?;
+        Ok(commit_hashes(commits))
+    }
+
+    /// Call a Conduit method, turning error responses into an appropriate `Error`.
+    async fn call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        params: &[(String, String)],
+    ) -> Result<T> {
+        let mut form = vec![("api.token", self.api_token.as_str())];
+        form.extend(
+            params
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str())),
+        );
+        let request = self
+            .http
+            .post(format!("{}/{method}", self.api_url))
+            .form(&form);
+
+        let res = Box::pin(request.send()).await?;
+        let status = res.status();
+        if !status.is_success() {
+            return Err(Error::Status(status));
+        }
+
+        let response: ConduitResponse<T> = Box::pin(res.json()).await?;
+        response.into_result()
+    }
+}
+
+/// Get the hashes of `commits`. Anything that isn't a hash is left out, since
+/// hashes are used to build revsets.
+fn commit_hashes(commits: SearchResults<Commit>) -> Vec<String> {
+    commits
+        .data
+        .into_iter()
+        .map(|commit| commit.fields.identifier)
+        .filter(|identifier| landing::is_hash(identifier))
+        .collect()
+}
+
+/// The envelope that every Conduit response is wrapped in.
+#[derive(Debug, Deserialize)]
+struct ConduitResponse<T> {
+    result: Option<T>,
+    error_code: Option<String>,
+    error_info: Option<String>,
+}
+
+impl<T> ConduitResponse<T> {
+    /// Turn the response into its result, or the error it reports.
+    fn into_result(self) -> Result<T> {
+        match (self.result, self.error_code) {
+            (_, Some(code)) => Err(Error::Api {
+                code,
+                message: self.error_info.unwrap_or_default(),
+            }),
+            (Some(result), None) => Ok(result),
+            (None, None) => Err(Error::ApiContract),
+        }
+    }
+}
+
+/// The results of a Conduit `*.search` method.
+#[derive(Debug, Deserialize)]
+struct SearchResults<T> {
+    data: Vec<T>,
+}
+
+/// A Differential revision, as returned by `differential.revision.search`.
+#[derive(Debug, Deserialize)]
+struct Revision {
+    phid: String,
+}
+
+/// A link between two objects, as returned by `edge.search`.
+#[derive(Debug, Deserialize)]
+struct Edge {
+    #[serde(rename = "destinationPHID")]
+    destination: String,
+}
+
+/// A commit, as returned by `diffusion.commit.search`.
+#[derive(Debug, Deserialize)]
+struct Commit {
+    fields: CommitFields,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommitFields {
+    /// The hash of the commit.
+    identifier: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{commit_hashes, Commit, ConduitResponse, Edge, Error, Revision, SearchResults};
+
+    fn response<T: serde::de::DeserializeOwned>(json: &str) -> super::Result<T> {
+        serde_json::from_str::<ConduitResponse<T>>(json)
+            .unwrap()
+            .into_result()
+    }
+
+    #[test]
+    fn revision_search() {
+        let revisions: SearchResults<Revision> = response(
+            r#"{"result": {"data": [{"id": 104001, "type": "DREV",
+                "phid": "PHID-DREV-abcdefghijklmnopqrst", "fields": {}}],
+                "cursor": {"limit": 100, "after": null}},
+                "error_code": null, "error_info": null}"#,
+        )
+        .unwrap();
+        assert_eq!(revisions.data[0].phid, "PHID-DREV-abcdefghijklmnopqrst");
+    }
+
+    #[test]
+    fn edge_search() {
+        let edges: SearchResults<Edge> = response(
+            r#"{"result": {"data": [
+                {"sourcePHID": "PHID-DREV-abcdefghijklmnopqrst", "edgeType": "revision.commit",
+                 "destinationPHID": "PHID-CMIT-aaaaaaaaaaaaaaaaaaaa"},
+                {"sourcePHID": "PHID-DREV-abcdefghijklmnopqrst", "edgeType": "revision.commit",
+                 "destinationPHID": "PHID-CMIT-bbbbbbbbbbbbbbbbbbbb"}],
+                "cursor": {"limit": 100, "after": null}},
+                "error_code": null, "error_info": null}"#,
+        )
+        .unwrap();
+        let destinations: Vec<&str> = edges
+            .data
+            .iter()
+            .map(|edge| edge.destination.as_str())
+            .collect();
+        assert_eq!(
+            destinations,
+            vec![
+                "PHID-CMIT-aaaaaaaaaaaaaaaaaaaa",
+                "PHID-CMIT-bbbbbbbbbbbbbbbbbbbb"
+            ]
+        );
+    }
+
+    #[test]
+    fn commit_search() {
+        let commits: SearchResults<Commit> = response(
+            r#"{"result": {"data": [
+                {"id": 1, "type": "CMIT", "phid": "PHID-CMIT-aaaaaaaaaaaaaaaaaaaa",
+                 "fields": {"identifier": "4b2a7b2d3e8e0123456789abcdef0123456789ab"}},
+                {"id": 2, "type": "CMIT", "phid": "PHID-CMIT-bbbbbbbbbbbbbbbbbbbb",
+                 "fields": {"identifier": "tip) + all("}}],
+                "cursor": {"limit": 100, "after": null}},
+                "error_code": null, "error_info": null}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            commit_hashes(commits),
+            vec!["4b2a7b2d3e8e0123456789abcdef0123456789ab"]
+        );
+    }
+
+    #[test]
+    fn error_response() {
+        let result: super::Result<SearchResults<Revision>> = response(
+            r#"{"result": null, "error_code": "ERR-INVALID-AUTH",
+                "error_info": "API token \"api-x\" has the wrong length."}"#,
+        );
+        assert!(matches!(
+            result,
+            Err(Error::Api { code, message })
+                if code == "ERR-INVALID-AUTH" && message.contains("wrong length")
+        ));
+    }
+
+    #[test]
+    fn empty_response() {
+        let result: super::Result<SearchResults<Revision>> =
+            response(r#"{"result": null, "error_code": null, "error_info": null}"#);
+        assert!(matches!(result, Err(Error::ApiContract)));
+    }
+}
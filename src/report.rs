@@ -0,0 +1,408 @@
+//! A record of what was found out and decided about each draft revision.
+
+use crate::{
+    bz::{BugResolution, BugStatus},
+    hg::Revision,
+    landing::{Match, NoSuccessor},
+    source::Kind,
+};
+use serde::Serialize;
+use std::fmt::{self, Write};
+
+/// How the results of a run should be written out.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Human readable text, with prompts.
+    Text,
+
+    /// One JSON object per draft revision, one per line.
+    Json,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!(
+                "unknown format {s:?}, expected \"text\" or \"json\""
+            )),
+        }
+    }
+}
+
+/// Everything known about a draft revision, and what was decided about it.
+#[derive(Debug, Serialize)]
+pub struct Record {
+    /// The hash of the draft revision.
+    pub hash: String,
+
+    /// The subject of the draft revision.
+    pub subject: Option<String>,
+
+    /// The bug mentioned in the revision's subject.
+    pub bug: Option<u64>,
+
+    /// The status of the bug.
+    pub status: Option<BugStatus>,
+
+    /// The resolution of the bug.
+    pub resolution: Option<BugResolution>,
+
+    /// The changeset that the revision landed as.
+    pub successor: Option<String>,
+
+    /// The repository that the successor landed in.
+    pub repository: Option<String>,
+
+    /// How the successor was matched to the revision.
+    pub matched_by: Option<Match>,
+
+    /// The landing source that this record came from.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<Kind>,
+
+    /// Descendants that have also landed, and can be pruned along with it.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stack: Vec<String>,
+
+    /// Descendants that would be left orphaned if the revision were pruned.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub orphans: Vec<String>,
+
+    /// What was decided about the revision.
+    #[serde(flatten)]
+    pub decision: Decision,
+
+    /// An error that prevented the revision from being examined or pruned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Record {
+    /// Start a record for `revision`. Until more is known, it is skipped for having no bug.
+    #[must_use]
+    pub fn new(revision: &Revision) -> Self {
+        Self {
+            hash: revision.hash.clone(),
+            subject: revision.subject().map(ToString::to_string),
+            bug: None,
+            status: None,
+            resolution: None,
+            successor: None,
+            repository: None,
+            matched_by: None,
+            source: None,
+            stack: Vec::new(),
+            orphans: Vec::new(),
+            decision: Decision::Skipped(SkipReason::NoBug),
+            error: None,
+        }
+    }
+
+    /// Mark this revision as skipped for `reason`.
+    #[must_use]
+    pub const fn skip(mut self, reason: SkipReason) -> Self {
+        self.decision = Decision::Skipped(reason);
+        self
+    }
+
+    /// The abbreviated hash of the revision.
+    #[must_use]
+    pub fn short_hash(&self) -> &str {
+        &self.hash[..12.min(self.hash.len())]
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}",
+            self.short_hash(),
+            self.subject.as_deref().unwrap_or("<no description>")
+        )?;
+        if let (Some(bug), Some(successor), Some(matched_by)) =
+            (&self.bug, &self.successor, self.matched_by)
+        {
+            write!(f, "\n  bug {bug}, successor {successor} ({matched_by})")?;
+        }
+        Ok(())
+    }
+}
+
+/// What was decided about a draft revision.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "decision", content = "reason", rename_all = "snake_case")]
+pub enum Decision {
+    /// The revision has landed, but nothing has been decided about it yet.
+    Prunable,
+
+    /// The revision will be pruned.
+    Accepted,
+
+    /// The revision was pruned.
+    Pruned,
+
+    /// Pruning the revision was attempted, but failed.
+    PruneFailed,
+
+    /// The revision would have been pruned, but this was a dry run.
+    WouldPrune,
+
+    /// The user chose not to prune the revision.
+    Declined,
+
+    /// The revision was not considered prunable.
+    Skipped(SkipReason),
+}
+
+/// Why a draft revision was not considered prunable.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    /// The revision does not mention a bug.
+    NoBug,
+
+    /// The bug could not be fetched, for example because it is confidential.
+    BugUnavailable,
+
+    /// The bug is not resolved as fixed.
+    BugNotFixed,
+
+    /// The revision has no Differential Revision to look up.
+    NoDifferential,
+
+    /// No landing in an accepted repository matches the revision.
+    NoLanding,
+
+    /// The revision landed, but was backed out and has not landed again.
+    BackedOut,
+
+    /// The successor is not in the local repository.
+    SuccessorMissing,
+
+    /// The successor does not satisfy the policy for pruning without prompting.
+    Policy,
+
+    /// Pruning the revision would leave descendants orphaned.
+    WouldOrphan,
+
+    /// A landing source failed to examine the revision.
+    SourceFailed,
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Prunable => write!(f, "prunable"),
+            Self::Accepted => write!(f, "accepted"),
+            Self::Pruned => write!(f, "pruned"),
+            Self::PruneFailed => write!(f, "prune failed"),
+            Self::WouldPrune => write!(f, "would prune"),
+            Self::Declined => write!(f, "declined"),
+            Self::Skipped(reason) => write!(f, "skipped: {reason}"),
+        }
+    }
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoBug => write!(f, "no bug in subject"),
+            Self::BugUnavailable => write!(f, "bug unavailable"),
+            Self::BugNotFixed => write!(f, "bug not resolved as fixed"),
+            Self::NoDifferential => write!(f, "no differential revision"),
+            Self::NoLanding => write!(f, "no matching landing"),
+            Self::BackedOut => write!(f, "landing was backed out"),
+            Self::SuccessorMissing => write!(f, "successor missing, pull needed"),
+            Self::Policy => write!(f, "not accepted by policy"),
+            Self::WouldOrphan => write!(f, "would orphan descendants"),
+            Self::SourceFailed => write!(f, "landing source failed"),
+        }
+    }
+}
+
+impl From<NoSuccessor> for SkipReason {
+    fn from(reason: NoSuccessor) -> Self {
+        match reason {
+            NoSuccessor::NoLanding => Self::NoLanding,
+            NoSuccessor::BackedOut => Self::BackedOut,
+        }
+    }
+}
+
+/// Lay out a table describing what was decided about each revision, followed
+/// by how many revisions had each outcome.
+#[must_use]
+pub fn table(records: &[Record]) -> String {
+    let rows: Vec<[String; 4]> = records
+        .iter()
+        .map(|record| {
+            [
+                record.short_hash().to_string(),
+                record
+                    .bug
+                    .map_or_else(|| "-".to_string(), |bug| bug.to_string()),
+                record.decision.to_string(),
+                record.subject.clone().unwrap_or_default(),
+            ]
+        })
+        .collect();
+    let header = ["REVISION", "BUG", "OUTCOME", "SUBJECT"].map(ToString::to_string);
+
+    let mut widths = [0; 3];
+    for row in std::iter::once(&header).chain(&rows) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut out = String::new();
+    for [hash, bug, outcome, subject] in std::iter::once(&header).chain(&rows) {
+        let _ = writeln!(
+            out,
+            "{hash:<0$}  {bug:<1$}  {outcome:<2$}  {subject}",
+            widths[0], widths[1], widths[2]
+        );
+    }
+
+    let mut counts: Vec<(Decision, usize)> = Vec::new();
+    for record in records {
+        match counts
+            .iter_mut()
+            .find(|(decision, _)| *decision == record.decision)
+        {
+            Some((_, count)) => *count += 1,
+            None => counts.push((record.decision, 1)),
+        }
+    }
+    let summary: Vec<String> = counts
+        .iter()
+        .map(|(decision, count)| format!("{count} {decision}"))
+        .collect();
+    let _ = writeln!(out, "\n{}", summary.join(", "));
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{table, Decision, Record, SkipReason};
+    use crate::{
+        bz::{BugResolution, BugStatus},
+        hg::Revision,
+        landing::Match,
+        source::Kind,
+    };
+
+    fn revision(hash: &str, subject: &str) -> Revision {
+        Revision::new(hash, &format!("{subject}\n\nMore details"))
+    }
+
+    fn landed(hash: &str, subject: &str, decision: Decision) -> Record {
+        Record {
+            bug: Some(1_690_000),
+            status: Some(BugStatus::Resolved),
+            resolution: Some(BugResolution::Fixed),
+            successor: Some("4b2a7b2d3e8e".to_string()),
+            repository: Some("mozilla-central".to_string()),
+            matched_by: Some(Match::Subject),
+            source: Some(Kind::Bugzilla),
+            decision,
+            ..Record::new(&revision(hash, subject))
+        }
+    }
+
+    #[test]
+    fn skipped_json() {
+        let record =
+            Record::new(&revision("0123456789ab", "Update the README")).skip(SkipReason::NoBug);
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            concat!(
+                r#"{"hash":"0123456789ab","subject":"Update the README","bug":null,"#,
+                r#""status":null,"resolution":null,"successor":null,"repository":null,"#,
+                r#""matched_by":null,"decision":"skipped","reason":"no_bug"}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn would_prune_json() {
+        let record = Record {
+            orphans: vec!["fedcba987654".to_string()],
+            ..landed(
+                "0123456789ab",
+                "Bug 1690000 - Add a schema",
+                Decision::WouldPrune,
+            )
+        };
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            concat!(
+                r#"{"hash":"0123456789ab","subject":"Bug 1690000 - Add a schema","#,
+                r#""bug":1690000,"status":"RESOLVED","resolution":"FIXED","#,
+                r#""successor":"4b2a7b2d3e8e","repository":"mozilla-central","#,
+                r#""matched_by":"subject","source":"bugzilla","orphans":["fedcba987654"],"#,
+                r#""decision":"would_prune"}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn pruned_json() {
+        let record = Record {
+            stack: vec!["fedcba987654".to_string()],
+            ..landed(
+                "0123456789ab",
+                "Bug 1690000 - Add a schema",
+                Decision::Pruned,
+            )
+        };
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            concat!(
+                r#"{"hash":"0123456789ab","subject":"Bug 1690000 - Add a schema","#,
+                r#""bug":1690000,"status":"RESOLVED","resolution":"FIXED","#,
+                r#""successor":"4b2a7b2d3e8e","repository":"mozilla-central","#,
+                r#""matched_by":"subject","source":"bugzilla","stack":["fedcba987654"],"#,
+                r#""decision":"pruned"}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn summary_table() {
+        let records = [
+            landed(
+                "0123456789abcdef",
+                "Bug 1690000 - Add a schema",
+                Decision::Pruned,
+            ),
+            Record::new(&revision("fedcba987654", "Update the README")),
+            landed(
+                "aaaaaaaaaaaa",
+                "Bug 1690000 - Validate recipes",
+                Decision::Pruned,
+            ),
+            Record {
+                bug: Some(42),
+                ..Record::new(&revision("bbbbbbbbbbbb", "Bug 42 - Not done yet"))
+                    .skip(SkipReason::BugNotFixed)
+            },
+        ];
+        assert_eq!(
+            table(&records),
+            "REVISION      BUG      OUTCOME                             SUBJECT\n\
+             0123456789ab  1690000  pruned                              Bug 1690000 - Add a schema\n\
+             fedcba987654  -        skipped: no bug in subject          Update the README\n\
+             aaaaaaaaaaaa  1690000  pruned                              Bug 1690000 - Validate recipes\n\
+             bbbbbbbbbbbb  42       skipped: bug not resolved as fixed  Bug 42 - Not done yet\n\
+             \n\
+             2 pruned, 1 skipped: no bug in subject, 1 skipped: bug not resolved as fixed\n"
+        );
+    }
+}
@@ -0,0 +1,125 @@
+//! The repositories that revisions are accepted as landing in, and where their
+//! changesets can be found.
+
+use crate::{
+    hg::{self, Hg, Revision},
+    landing::{Landing, LOCAL, MOZILLA_CENTRAL},
+};
+
+type Result<T> = std::result::Result<T, hg::Error>;
+
+/// Where the changesets of a landing repository can be found.
+#[derive(Debug)]
+pub enum Location {
+    /// The repository that drafts are being pruned from.
+    Here,
+
+    /// Another local repository.
+    Path(Hg),
+
+    /// A remote repository, such as a path name from hgrc or a URL.
+    Remote(String),
+}
+
+/// A repository that revisions are accepted as landing in.
+#[derive(Debug)]
+pub struct Repository {
+    /// The path of the repository on hg.mozilla.org, such as
+    /// `mozilla-central` or `integration/autoland`.
+    pub name: String,
+
+    /// Where successors that landed in this repository can be found.
+    pub location: Location,
+}
+
+/// Every repository that revisions are accepted as landing in.
+#[derive(Debug)]
+pub struct Repositories<'a> {
+    repositories: Vec<Repository>,
+    here: &'a Hg,
+}
+
+impl<'a> Repositories<'a> {
+    /// Accept landings in `repositories`, where `here` is the repository that
+    /// drafts are being pruned from.
+    #[must_use]
+    pub const fn new(repositories: Vec<Repository>, here: &'a Hg) -> Self {
+        Self { repositories, here }
+    }
+
+    /// Only accept landings in mozilla-central, which must have been pulled
+    /// into `here`, the repository that drafts are being pruned from.
+    #[must_use]
+    pub fn mozilla_central(here: &'a Hg) -> Self {
+        Self::new(
+            vec![Repository {
+                name: MOZILLA_CENTRAL.to_string(),
+                location: Location::Here,
+            }],
+            here,
+        )
+    }
+
+    /// The names of the accepted repositories.
+    #[must_use]
+    pub fn names(&self) -> Vec<&str> {
+        self.repositories
+            .iter()
+            .map(|repository| repository.name.as_str())
+            .collect()
+    }
+
+    /// Whether successors that landed in the repository `name` are in the
+    /// repository that drafts are being pruned from, so they can be recorded
+    /// as the successors of pruned drafts. Landings found locally always are.
+    #[must_use]
+    pub fn is_here(&self, name: &str) -> bool {
+        name == LOCAL
+            || self
+                .get(name)
+                .is_some_and(|repository| matches!(repository.location, Location::Here))
+    }
+
+    /// Get the changesets for as many of `landings` as can be found locally.
+    ///
+    /// # Errors
+    /// Returns an error if Mercurial fails to look up the changesets.
+    pub async fn find_all(&self, landings: &[Landing]) -> Result<Vec<Revision>> {
+        let mut found = Vec::new();
+        for repository in &self.repositories {
+            let hg = match &repository.location {
+                Location::Here => self.here,
+                Location::Path(hg) => hg,
+                Location::Remote(_) => continue,
+            };
+            let hashes = landings
+                .iter()
+                .filter(|landing| landing.repository == repository.name)
+                .map(|landing| landing.hash.as_str());
+            found.extend(hg.find_all(hashes).await?);
+        }
+        Ok(found)
+    }
+
+    /// Check whether the changeset for `landing` is present where its
+    /// repository is expected to be found.
+    ///
+    /// # Errors
+    /// Returns an error if Mercurial fails to look up the changeset.
+    pub async fn contains(&self, landing: &Landing) -> Result<bool> {
+        match self
+            .get(&landing.repository)
+            .map(|repository| &repository.location)
+        {
+            Some(Location::Here) | None => self.here.is_known(&landing.hash).await,
+            Some(Location::Path(hg)) => hg.is_known(&landing.hash).await,
+            Some(Location::Remote(remote)) => self.here.remote_has(remote, &landing.hash).await,
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<&Repository> {
+        self.repositories
+            .iter()
+            .find(|repository| repository.name == name)
+    }
+}
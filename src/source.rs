@@ -0,0 +1,480 @@
+//! Places that evidence of a draft revision having landed can be found.
+
+use crate::{
+    bz::{self, ApiBug, BugDetail},
+    cache::{self, CachingClient},
+    hg::{self, Hg, Revision},
+    landing, phab,
+    report::{Decision, Record, SkipReason},
+    repository::Repositories,
+};
+use futures::future::{self, BoxFuture, FutureExt};
+use serde::Serialize;
+use std::{collections::HashMap, fmt};
+use thiserror::Error;
+
+/// A problem that prevented a landing source from examining a revision.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// Bug details were requested, but Bugzilla didn't return them
+    #[error("No details fetched for bug {0}")]
+    MissingDetails(u64),
+
+    /// Errors from the Bugzilla cache are passed through transparently.
+    #[error(transparent)]
+    Cache(#[from] cache::Error),
+
+    /// Errors from Mercurial are passed through transparently.
+    #[error(transparent)]
+    Hg(#[from] hg::Error),
+
+    /// Errors from Phabricator are passed through transparently.
+    #[error(transparent)]
+    Phabricator(#[from] phab::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The details of every bug mentioned by a draft revision, fetched up front.
+pub type Details = HashMap<u64, std::result::Result<BugDetail, cache::Error>>;
+
+/// The kinds of landing source that can be enabled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    /// Landings mentioned in the comments of the revision's bugs.
+    Bugzilla,
+
+    /// Public changesets in the local repository.
+    Local,
+
+    /// Commits that Phabricator says the revision's Differential revision landed as.
+    Phabricator,
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bugzilla => write!(f, "bugzilla"),
+            Self::Local => write!(f, "local"),
+            Self::Phabricator => write!(f, "phabricator"),
+        }
+    }
+}
+
+impl std::str::FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "bugzilla" => Ok(Self::Bugzilla),
+            "local" => Ok(Self::Local),
+            "phabricator" => Ok(Self::Phabricator),
+            _ => Err(format!(
+                "unknown landing source {s:?}, expected \"bugzilla\", \"local\" or \"phabricator\""
+            )),
+        }
+    }
+}
+
+/// Somewhere to look for the changeset that a draft revision landed as.
+pub trait LandingSource: fmt::Debug + Send + Sync {
+    /// Which kind of source this is.
+    fn kind(&self) -> Kind;
+
+    /// Look for the successor of `revision`. The record returned is
+    /// `Prunable` if a successor was found, with `matched_by` describing how
+    /// confident the match is, and otherwise says why it was skipped.
+    fn examine<'a>(&'a self, revision: &'a Revision) -> BoxFuture<'a, Result<Record>>;
+}
+
+/// Examine `revision` with every source, and combine what they found.
+///
+/// The most confident successor wins, preferring earlier sources when they are
+/// equally confident. If no source found a successor, the first source's
+/// reason for skipping the revision is used. A source that fails to examine
+/// the revision doesn't stop the others, and its error is recorded as its
+/// reason for skipping the revision.
+pub async fn examine(sources: &[Box<dyn LandingSource + '_>], revision: &Revision) -> Record {
+    let records = future::join_all(sources.iter().map(|source| async move {
+        source.examine(revision).await.unwrap_or_else(|err| Record {
+            source: Some(source.kind()),
+            error: Some(err.to_string()),
+            ..Record::new(revision).skip(SkipReason::SourceFailed)
+        })
+    }))
+    .await;
+    combine(records).unwrap_or_else(|| Record::new(revision))
+}
+
+/// Pick the best of several records about the same revision. Prunable records
+/// are preferred, then those whose successor only needs to be pulled.
+fn combine(records: Vec<Record>) -> Option<Record> {
+    let mut best: Option<Record> = None;
+    for record in records {
+        let better = best
+            .as_ref()
+            .is_none_or(|best| match (&record.decision, &best.decision) {
+                (Decision::Prunable, Decision::Prunable) => record.matched_by > best.matched_by,
+                (Decision::Prunable, _) => true,
+                (Decision::Skipped(SkipReason::SuccessorMissing), Decision::Skipped(reason)) => {
+                    *reason != SkipReason::SuccessorMissing
+                }
+                _ => false,
+            });
+        if better {
+            best = Some(record);
+        }
+    }
+    best
+}
+
+/// Finds landings in the comments of the bugs that a revision mentions.
+#[derive(Debug)]
+pub struct Bugzilla<'a> {
+    client: &'a bz::Client,
+    bugs: &'a CachingClient<'a>,
+    details: &'a Details,
+    repositories: &'a Repositories<'a>,
+}
+
+impl<'a> Bugzilla<'a> {
+    /// Create a source that looks up bugs with `client` through the cache in
+    /// `bugs`, using bug details that were already fetched, and accepts
+    /// landings in any of `repositories`.
+    #[must_use]
+    pub const fn new(
+        client: &'a bz::Client,
+        bugs: &'a CachingClient<'a>,
+        details: &'a Details,
+        repositories: &'a Repositories<'a>,
+    ) -> Self {
+        Self {
+            client,
+            bugs,
+            details,
+            repositories,
+        }
+    }
+
+    /// If the revision mentions several bugs, the first bug it appears to have
+    /// landed on is used.
+    async fn examine_revision(&self, revision: &Revision) -> Result<Record> {
+        let mut first = None;
+        for bug in revision.bugs() {
+            let record = self
+                .examine_bug(revision, bug.with_api(self.client))
+                .await?;
+            if let Decision::Prunable | Decision::Skipped(SkipReason::SuccessorMissing) =
+                record.decision
+            {
+                return Ok(record);
+            }
+            first.get_or_insert(record);
+        }
+        Ok(first.unwrap_or_else(|| Record::new(revision).skip(SkipReason::NoBug)))
+    }
+
+    /// Work out whether a draft revision has landed on a specific bug.
+    async fn examine_bug(&self, revision: &Revision, bug: ApiBug<'_>) -> Result<Record> {
+        let mut record = Record {
+            bug: Some(bug.id),
+            source: Some(Kind::Bugzilla),
+            ..Record::new(revision)
+        };
+
+        // Skip bugs that aren't resolved as fixed
+        match self.details.get(&bug.id) {
+            Some(Ok(detail)) => {
+                record.status = Some(detail.status);
+                record.resolution = detail.resolution;
+                if !detail.is_fixed() {
+                    return Ok(record.skip(SkipReason::BugNotFixed));
+                }
+            }
+            Some(Err(err)) => {
+                record.error = Some(err.to_string());
+                return Ok(record.skip(SkipReason::BugUnavailable));
+            }
+            None => return Err(Error::MissingDetails(bug.id)),
+        }
+
+        // Find the changeset in an accepted repository that the revision landed as
+        let comments = self.bugs.comments(&bug).await?;
+        let names = self.repositories.names();
+        let landed = self
+            .repositories
+            .find_all(&landing::accepted_landings(&comments, &names))
+            .await?;
+        let successor = match landing::find_successor(revision, &comments, &names, &landed) {
+            Ok(successor) => successor,
+            Err(reason) => return Ok(record.skip(reason.into())),
+        };
+        record.successor = Some(successor.landing.hash.clone());
+        record.repository = Some(successor.landing.repository.clone());
+        record.matched_by = Some(successor.matched_by);
+
+        // Revisions can only be pruned to successors that are where their
+        // repository is expected to be
+        if !self.repositories.contains(&successor.landing).await? {
+            return Ok(record.skip(SkipReason::SuccessorMissing));
+        }
+
+        record.decision = Decision::Prunable;
+        Ok(record)
+    }
+}
+
+impl LandingSource for Bugzilla<'_> {
+    fn kind(&self) -> Kind {
+        Kind::Bugzilla
+    }
+
+    fn examine<'a>(&'a self, revision: &'a Revision) -> BoxFuture<'a, Result<Record>> {
+        self.examine_revision(revision).boxed()
+    }
+}
+
+/// Finds landings among the public changesets in the local repository.
+#[derive(Debug)]
+pub struct Local<'a> {
+    details: &'a Details,
+    hg: &'a Hg,
+}
+
+impl<'a> Local<'a> {
+    /// Create a source that searches the repository `hg`. Bug details that
+    /// were already fetched are used to rule out revisions whose bug is known
+    /// not to be fixed, but missing details are not a problem.
+    #[must_use]
+    pub const fn new(details: &'a Details, hg: &'a Hg) -> Self {
+        Self { details, hg }
+    }
+
+    async fn examine_revision(&self, revision: &Revision) -> Result<Record> {
+        let mut record = Record {
+            bug: revision.bug().map(|bug| bug.id),
+            source: Some(Kind::Local),
+            ..Record::new(revision)
+        };
+        if record.bug.is_none() && revision.differential().is_none() {
+            return Ok(record.skip(SkipReason::NoBug));
+        }
+
+        if let Some(Ok(detail)) = record.bug.and_then(|id| self.details.get(&id)) {
+            record.status = Some(detail.status);
+            record.resolution = detail.resolution;
+            if !detail.is_fixed() {
+                return Ok(record.skip(SkipReason::BugNotFixed));
+            }
+        }
+
+        let public = self.hg.find_public(revision).await?;
+        let successor = match landing::find_local_successor(revision, &public) {
+            Ok(successor) => successor,
+            Err(reason) => return Ok(record.skip(reason.into())),
+        };
+        record.successor = Some(successor.landing.hash);
+        record.repository = Some(successor.landing.repository);
+        record.matched_by = Some(successor.matched_by);
+        record.decision = Decision::Prunable;
+        Ok(record)
+    }
+}
+
+impl LandingSource for Local<'_> {
+    fn kind(&self) -> Kind {
+        Kind::Local
+    }
+
+    fn examine<'a>(&'a self, revision: &'a Revision) -> BoxFuture<'a, Result<Record>> {
+        self.examine_revision(revision).boxed()
+    }
+}
+
+/// Asks Phabricator which commits a revision's Differential revision landed as.
+#[derive(Debug)]
+pub struct Phabricator<'a> {
+    client: &'a phab::Client,
+    hg: &'a Hg,
+}
+
+impl<'a> Phabricator<'a> {
+    /// Create a source that asks Phabricator with `client`, and looks for the
+    /// landed commits in the repository `hg`.
+    #[must_use]
+    pub const fn new(client: &'a phab::Client, hg: &'a Hg) -> Self {
+        Self { client, hg }
+    }
+
+    async fn examine_revision(&self, revision: &Revision) -> Result<Record> {
+        let mut record = Record {
+            bug: revision.bug().map(|bug| bug.id),
+            source: Some(Kind::Phabricator),
+            ..Record::new(revision)
+        };
+        let Some(differential) = revision.differential() else {
+            return Ok(record.skip(SkipReason::NoDifferential));
+        };
+
+        let commits = self.client.landed_commits(differential).await?;
+        let landed = self.hg.find_all(commits.iter().map(String::as_str)).await?;
+        // Phabricator doesn't know about backouts, so check for them locally.
+        if landed.is_empty() {
+            // Commit searches list the newest commits first.
+            return Ok(match commits.first() {
+                Some(hash) => Record {
+                    successor: Some(hash.clone()),
+                    matched_by: Some(landing::Match::Differential),
+                    ..record.skip(SkipReason::SuccessorMissing)
+                },
+                None => record.skip(SkipReason::NoLanding),
+            });
+        }
+        // Only public changesets can be successors, and those that land later
+        // are where backouts would be.
+        let public = self.hg.find_public(revision).await?;
+        let Some(index) = last_landing(&landed, &public) else {
+            return Ok(record.skip(SkipReason::NoLanding));
+        };
+        let found = &public[index];
+        record.successor = Some(found.hash.clone());
+        record.repository = Some(landing::LOCAL.to_string());
+        record.matched_by = Some(landing::Match::Differential);
+
+        let later = &public[index + 1..];
+        let bugs: Vec<u64> = revision.bugs().iter().map(|bug| bug.id).collect();
+        if landing::is_backed_out(found, later, &bugs) {
+            return Ok(record.skip(SkipReason::BackedOut));
+        }
+
+        record.decision = Decision::Prunable;
+        Ok(record)
+    }
+}
+
+/// Find the position of the last of the `public` changesets that is one of
+/// the `landed` ones. A revision that was backed out and relanded has landed
+/// more than once, and only its last landing can still be in place.
+fn last_landing(landed: &[Revision], public: &[Revision]) -> Option<usize> {
+    public
+        .iter()
+        .rposition(|changeset| landed.iter().any(|landing| landing.hash == changeset.hash))
+}
+
+impl LandingSource for Phabricator<'_> {
+    fn kind(&self) -> Kind {
+        Kind::Phabricator
+    }
+
+    fn examine<'a>(&'a self, revision: &'a Revision) -> BoxFuture<'a, Result<Record>> {
+        self.examine_revision(revision).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{examine, last_landing, Error, Kind, LandingSource, Result};
+    use crate::{
+        hg::Revision,
+        landing,
+        report::{Decision, Record, SkipReason},
+    };
+    use futures::{
+        executor::block_on,
+        future::{self, BoxFuture, FutureExt},
+    };
+
+    /// A source that fails to examine anything.
+    #[derive(Debug)]
+    struct Failing;
+
+    impl LandingSource for Failing {
+        fn kind(&self) -> Kind {
+            Kind::Bugzilla
+        }
+
+        fn examine<'a>(&'a self, _revision: &'a Revision) -> BoxFuture<'a, Result<Record>> {
+            future::ready(Err(Error::MissingDetails(1))).boxed()
+        }
+    }
+
+    /// A source that finds a successor for everything.
+    #[derive(Debug)]
+    struct Found;
+
+    impl LandingSource for Found {
+        fn kind(&self) -> Kind {
+            Kind::Local
+        }
+
+        fn examine<'a>(&'a self, revision: &'a Revision) -> BoxFuture<'a, Result<Record>> {
+            future::ready(Ok(Record {
+                successor: Some("abcdef".to_string()),
+                source: Some(Kind::Local),
+                decision: Decision::Prunable,
+                ..Record::new(revision)
+            }))
+            .boxed()
+        }
+    }
+
+    fn revision() -> Revision {
+        Revision::new("0123456789ab", "Bug 1 - Fix the thing")
+    }
+
+    #[test]
+    fn failing_source_is_skipped() {
+        let sources: Vec<Box<dyn LandingSource>> = vec![Box::new(Failing), Box::new(Found)];
+        let record = block_on(examine(&sources, &revision()));
+        assert_eq!(record.decision, Decision::Prunable);
+        assert_eq!(record.source, Some(Kind::Local));
+        assert_eq!(record.successor.as_deref(), Some("abcdef"));
+    }
+
+    #[test]
+    fn failure_is_recorded() {
+        let sources: Vec<Box<dyn LandingSource>> = vec![Box::new(Failing)];
+        let record = block_on(examine(&sources, &revision()));
+        assert_eq!(record.decision, Decision::Skipped(SkipReason::SourceFailed));
+        assert_eq!(record.source, Some(Kind::Bugzilla));
+        assert_eq!(
+            record.error.as_deref(),
+            Some("No details fetched for bug 1")
+        );
+    }
+
+    #[test]
+    fn relanded_revision_succeeds_to_last_landing() {
+        let fix = "Bug 1 - Fix the thing r=reviewer";
+        let public = vec![
+            Revision::new("aaaaaaaaaaaa", fix),
+            Revision::new(
+                "bbbbbbbbbbbb",
+                "Backed out changeset aaaaaaaaaaaa (bug 1) for causing failures",
+            ),
+            Revision::new("cccccccccccc", fix),
+        ];
+
+        // Commit searches list the newest commits first
+        let landed = vec![
+            Revision::new("cccccccccccc", fix),
+            Revision::new("aaaaaaaaaaaa", fix),
+        ];
+        let index = last_landing(&landed, &public).unwrap();
+        assert_eq!(public[index].hash, "cccccccccccc");
+        assert!(!landing::is_backed_out(
+            &public[index],
+            &public[index + 1..],
+            &[1]
+        ));
+        assert!(landing::is_backed_out(&public[0], &public[1..], &[1]));
+    }
+
+    #[test]
+    fn unpublished_landings_are_not_found() {
+        let public = vec![Revision::new("aaaaaaaaaaaa", "Bug 2 - Something else")];
+        let landed = vec![Revision::new("cccccccccccc", "Bug 1 - Fix the thing")];
+        assert_eq!(last_landing(&landed, &public), None);
+    }
+}
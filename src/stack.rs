@@ -0,0 +1,237 @@
+//! Stacks of draft revisions, and what pruning part of a stack does to the
+//! rest of it.
+
+use crate::{hg::Revision, report::Record};
+use futures::stream::{self, Stream, StreamExt};
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// How the mutable revisions in a repository descend from each other.
+#[derive(Debug)]
+pub struct Stacks {
+    children: HashMap<String, Vec<String>>,
+    examined: HashSet<String>,
+}
+
+impl Stacks {
+    /// Map the stacks formed by `mutable`, every revision that isn't public
+    /// or obsolete, of which `examined` are the ones that will have records.
+    #[must_use]
+    pub fn new(mutable: &[Revision], examined: &[Revision]) -> Self {
+        let mut children: HashMap<String, Vec<String>> = HashMap::new();
+        for revision in mutable {
+            for parent in &revision.parents {
+                children
+                    .entry(parent.clone())
+                    .or_default()
+                    .push(revision.hash.clone());
+            }
+        }
+        Self {
+            children,
+            examined: examined
+                .iter()
+                .map(|revision| revision.hash.clone())
+                .collect(),
+        }
+    }
+
+    /// Get every mutable descendant of the revision `hash`, nearest first.
+    #[must_use]
+    pub fn descendants(&self, hash: &str) -> Vec<&str> {
+        let mut found: Vec<&str> = Vec::new();
+        let mut queue: VecDeque<&str> = VecDeque::from(vec![hash]);
+        while let Some(hash) = queue.pop_front() {
+            for child in self.children.get(hash).into_iter().flatten() {
+                if !found.contains(&child.as_str()) {
+                    found.push(child);
+                    queue.push_back(child);
+                }
+            }
+        }
+        found
+    }
+}
+
+/// Put `revisions` in topological order, with every revision after its
+/// ancestors. Revision numbers already are, but revsets such as `a + b` can
+/// list revisions in any order.
+pub fn sort(revisions: &mut [Revision]) {
+    revisions.sort_by_key(|revision| revision.rev);
+}
+
+/// Fill in the `stack` and `orphans` of each record that `will_prune`, which
+/// says whether a revision would be pruned if nothing else were considered.
+///
+/// Records must be in topological order, as `sort` leaves them, so records are
+/// read ahead until those of every descendant have arrived. Records are still
+/// produced in the order they were read.
+pub fn annotate<'a, S, E, F>(
+    records: S,
+    stacks: &'a Stacks,
+    will_prune: F,
+) -> impl Stream<Item = Result<Record, E>> + 'a
+where
+    S: Stream<Item = Result<Record, E>> + Unpin + 'a,
+    E: 'a,
+    F: Fn(&Record) -> bool + 'a,
+{
+    let state = (records, VecDeque::new(), will_prune);
+    stream::unfold(
+        state,
+        move |(mut records, mut ahead, will_prune)| async move {
+            let mut record = match ahead.pop_front() {
+                Some(record) => record,
+                None => match records.next().await? {
+                    Ok(record) => record,
+                    Err(err) => return Some((Err(err), (records, ahead, will_prune))),
+                },
+            };
+            if !will_prune(&record) {
+                return Some((Ok(record), (records, ahead, will_prune)));
+            }
+
+            let descendants = stacks.descendants(&record.hash);
+            while descendants.iter().any(|hash| {
+                stacks.examined.contains(*hash) && !ahead.iter().any(|r: &Record| r.hash == *hash)
+            }) {
+                match records.next().await {
+                    Some(Ok(next)) => ahead.push_back(next),
+                    Some(Err(err)) => {
+                        ahead.push_front(record);
+                        return Some((Err(err), (records, ahead, will_prune)));
+                    }
+                    None => break,
+                }
+            }
+
+            let (stack, orphans): (Vec<&str>, Vec<&str>) =
+                descendants.into_iter().partition(|hash| {
+                    ahead
+                        .iter()
+                        .any(|next| next.hash == *hash && will_prune(next))
+                });
+            if orphans.is_empty() {
+                record.stack = stack.into_iter().map(ToString::to_string).collect();
+            } else {
+                record.orphans = orphans.into_iter().map(ToString::to_string).collect();
+            }
+            Some((Ok(record), (records, ahead, will_prune)))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{annotate, sort, Stacks};
+    use crate::{
+        hg::Revision,
+        report::{Decision, Record},
+    };
+    use futures::{executor::block_on, stream, StreamExt};
+
+    fn revision(hash: &str, parents: &[&str]) -> Revision {
+        Revision {
+            rev: Some(u64::from(hash.as_bytes()[0])),
+            parents: parents.iter().map(ToString::to_string).collect(),
+            ..Revision::new(hash, &format!("Bug 1 - {hash}"))
+        }
+    }
+
+    fn record(revision: &Revision, prunable: bool) -> Record {
+        let record = Record::new(revision);
+        if prunable {
+            Record {
+                decision: Decision::Prunable,
+                ..record
+            }
+        } else {
+            record
+        }
+    }
+
+    fn annotated(stacks: &Stacks, records: Vec<Record>) -> Vec<Record> {
+        let records = stream::iter(records.into_iter().map(Ok::<_, ()>));
+        let annotated = annotate(records, stacks, |record| {
+            record.decision == Decision::Prunable
+        });
+        block_on(annotated.map(Result::unwrap).collect())
+    }
+
+    #[test]
+    fn descendants() {
+        let revisions = [
+            revision("a", &[]),
+            revision("b", &["a"]),
+            revision("c", &["b"]),
+            revision("d", &["a"]),
+            revision("e", &["c", "d"]),
+        ];
+        let stacks = Stacks::new(&revisions, &revisions);
+        assert_eq!(stacks.descendants("a"), vec!["b", "d", "c", "e"]);
+        assert_eq!(stacks.descendants("c"), vec!["e"]);
+        assert!(stacks.descendants("e").is_empty());
+    }
+
+    #[test]
+    fn landed_stack() {
+        let revisions = [
+            revision("a", &[]),
+            revision("b", &["a"]),
+            revision("c", &["b"]),
+        ];
+        let stacks = Stacks::new(&revisions, &revisions);
+        let records = revisions.iter().map(|r| record(r, true)).collect();
+        let records = annotated(&stacks, records);
+        assert_eq!(records[0].stack, vec!["b", "c"]);
+        assert_eq!(records[1].stack, vec!["c"]);
+        assert!(records[2].stack.is_empty());
+        assert!(records.iter().all(|record| record.orphans.is_empty()));
+    }
+
+    #[test]
+    fn reversed_stack() {
+        let mut revisions = [
+            revision("c", &["b"]),
+            revision("b", &["a"]),
+            revision("a", &[]),
+        ];
+        sort(&mut revisions);
+        let hashes: Vec<&str> = revisions.iter().map(|r| r.hash.as_str()).collect();
+        assert_eq!(hashes, vec!["a", "b", "c"]);
+
+        let stacks = Stacks::new(&revisions, &revisions);
+        let records = revisions.iter().map(|r| record(r, true)).collect();
+        let records = annotated(&stacks, records);
+        assert_eq!(records[0].stack, vec!["b", "c"]);
+        assert_eq!(records[1].stack, vec!["c"]);
+        assert!(records.iter().all(|record| record.orphans.is_empty()));
+    }
+
+    #[test]
+    fn orphans() {
+        let revisions = [
+            revision("a", &[]),
+            revision("b", &["a"]),
+            revision("c", &["b"]),
+        ];
+        let stacks = Stacks::new(&revisions, &revisions);
+        let records = vec![
+            record(&revisions[0], true),
+            record(&revisions[1], false),
+            record(&revisions[2], true),
+        ];
+        let records = annotated(&stacks, records);
+        assert_eq!(records[0].orphans, vec!["b"]);
+        assert!(records[0].stack.is_empty());
+        assert!(records[1].orphans.is_empty());
+        assert!(records[2].orphans.is_empty());
+    }
+
+    #[test]
+    fn unexamined_descendants_are_orphaned() {
+        let mutable = [revision("a", &[]), revision("b", &["a"])];
+        let stacks = Stacks::new(&mutable, &mutable[..1]);
+        let records = annotated(&stacks, vec![record(&mutable[0], true)]);
+        assert_eq!(records[0].orphans, vec!["b"]);
+    }
+}